@@ -1,48 +1,81 @@
+//! Main writing functions for the book
+use std::collections::HashMap;
 use std::io::Write;
-///! Main writing functions for the book
 use std::path::Path;
 
 use itertools::Itertools;
+use serde::Deserialize;
 
-use crate::pages::{set_section_title, Page};
-use crate::replace;
+use crate::layouts::{Layout, Slot, MAX_SLOTS, PHOTO_AREA_HEIGHT};
+use crate::locale::Language;
+use crate::page_format::PageGeometry;
+use crate::pages::{set_page_headers, Page};
+use crate::profile::PrintProfile;
+use crate::specs::{FolderSpec, HeroFit, PageSpec};
 use crate::BookInfo;
 use crate::FolderInfo;
+use crate::ImageInfo;
 use crate::LayoutReq;
 use crate::PageInfo;
+use crate::PageKind;
+use crate::{replace, tex_path};
 
 fn handle_title_image(
     toplevel_text: &mut String,
     im_path: Option<&Path>,
+    out_folder: &Path,
 ) -> std::io::Result<()> {
-    if let Some(im_path) = im_path {
-        if let Some(im_path) = im_path.canonicalize()?.to_str() {
-            replace(
-                toplevel_text,
-                "PHOTOTEX_TITLE_IMAGE_COMMAND",
-                &format!(
-                    "\\includegraphics[width=0.90\\textwidth,\
-                     height=0.70\\textheight,\
-                     keepaspectratio]{{{}}}",
-                    im_path,
-                ),
-            )
-            .unwrap();
-            Ok(())
-        } else {
-            log::error!(
-                "could not include image path {:?} in title page: utf-8 failed",
-                im_path,
-            );
-            Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "utf-8 error",
-            ))
+    let command = match im_path {
+        Some(im_path) => format!(
+            "\\includegraphics[width=0.90\\textwidth,\
+             height=0.70\\textheight,\
+             keepaspectratio]{{{}}}",
+            tex_path(im_path, out_folder)?,
+        ),
+        None => String::new(),
+    };
+    replace(toplevel_text, "PHOTOTEX_TITLE_IMAGE_COMMAND", &command).unwrap();
+    Ok(())
+}
+
+/// Maximum number of photos on a mosaic filler page
+const MOSAIC_SIZE: usize = 20;
+
+/// Black background of the pages, extending into the bleed. With crop marks,
+/// it only covers the bleed box, and the marks are drawn around it.
+fn page_background(geometry: &PageGeometry) -> String {
+    if !geometry.crop_marks {
+        return "\\pagecolor{black}".to_string();
+    }
+    let (left, bottom, right, top) = geometry.bleed_box();
+    let (trim_left, trim_bottom, trim_right, trim_top) = geometry.trim_box();
+    // the marks are separated from the bleed and the paper edges
+    let gap = 1.;
+    let length = geometry.marks_space() - 2. * gap;
+    let thickness = 0.25;
+    let rule = |x: f32, y: f32, width: f32, height: f32| {
+        format!(
+            "  \\put({},{}){{\\rule{{{}mm}}{{{}mm}}}}%\n",
+            x, y, width, height
+        )
+    };
+    // the background, then the marks
+    let mut picture = rule(left, bottom, right - left, top - bottom);
+    for &x in &[trim_left, trim_right] {
+        for &y in &[gap, top + gap] {
+            picture.push_str(&rule(x - thickness / 2., y, thickness, length));
         }
-    } else {
-        replace(toplevel_text, "PHOTOTEX_TITLE_IMAGE_COMMAND", "").unwrap();
-        Ok(())
     }
+    for &y in &[trim_bottom, trim_top] {
+        for &x in &[gap, right + gap] {
+            picture.push_str(&rule(x, y - thickness / 2., length, thickness));
+        }
+    }
+    format!(
+        "\\AddToShipoutPictureBG{{%\n  \
+         \\setlength{{\\unitlength}}{{1mm}}%\n  \\color{{black}}%\n{}}}",
+        picture
+    )
 }
 
 pub fn write_toplevel(
@@ -51,9 +84,58 @@ pub fn write_toplevel(
     page_infos: &[PageInfo],
 ) -> std::io::Result<String> {
     let mut toplevel_text = include_str!("../data/toplevel.tex").to_string();
-    handle_title_image(&mut toplevel_text, book_info.title_im_path)?;
-    replace(&mut toplevel_text, "PHOTOTEX_TITLE_STRING", book_info.title)
-        .unwrap();
+    handle_title_image(
+        &mut toplevel_text,
+        book_info.title_im_path,
+        out_folder,
+    )?;
+    replace(
+        &mut toplevel_text,
+        "PHOTOTEX_TITLE_STRING",
+        &book_info.title.to_latex(),
+    )
+    .unwrap();
+    let geometry = book_info.page_geometry;
+    let (paper_width, paper_height) = geometry.paper_dims();
+    replace(
+        &mut toplevel_text,
+        "PHOTOTEX_PAPER_WIDTH",
+        &format!("{}mm", paper_width),
+    )
+    .unwrap();
+    replace(
+        &mut toplevel_text,
+        "PHOTOTEX_PAPER_HEIGHT",
+        &format!("{}mm", paper_height),
+    )
+    .unwrap();
+    // the margins are counted from the trimmed page
+    let margins = book_info.margins;
+    let (trim_left, trim_bottom, _, _) = geometry.trim_box();
+    replace(
+        &mut toplevel_text,
+        "PHOTOTEX_MARGINS",
+        &format!(
+            "outer={}mm, inner={}mm, top={}mm, bottom={}mm",
+            margins.outer + trim_left,
+            margins.inner + trim_left,
+            margins.top + trim_bottom,
+            margins.bottom + trim_bottom,
+        ),
+    )
+    .unwrap();
+    replace(
+        &mut toplevel_text,
+        "PHOTOTEX_BACKGROUND",
+        &page_background(&geometry),
+    )
+    .unwrap();
+    replace(
+        &mut toplevel_text,
+        "PHOTOTEX_BABEL_LANGUAGE",
+        book_info.language.babel_name(),
+    )
+    .unwrap();
     replace(
         &mut toplevel_text,
         "PHOTOTEX_TITLE_FONT_SIZE",
@@ -68,11 +150,8 @@ pub fn write_toplevel(
     .unwrap();
     let mut page_includes = String::new();
     for page in page_infos {
-        if let Some(path) = page.path.canonicalize()?.to_str() {
-            page_includes.push_str(&format!("\\input{{{}}}\n", path));
-        } else {
-            log::error!("could not include page {:?}", page.path);
-        }
+        let path = tex_path(&page.path, out_folder)?;
+        page_includes.push_str(&format!("\\input{{{}}}\n", path));
     }
     replace(
         &mut toplevel_text,
@@ -102,121 +181,1360 @@ pub fn write_toplevel(
     Ok(top_file_name.into())
 }
 
+fn is_landscape(im: &ImageInfo) -> bool {
+    im.rotated_dims.0 >= im.rotated_dims.1
+}
+
+/// Maximum number of images on a page
+const MAX_PER_PAGE: usize = MAX_SLOTS;
+
+/// Cost of adding a page. It is compared to the sizes of the photos, as
+/// fractions of the text area they cover.
+const PAGE_COST: f32 = 0.25;
+/// Size past which a photo is not worth more, so that a page with a large
+/// photo is not better than a page with two half page photos
+const MAX_PHOTO_SIZE: f32 = 0.3;
+/// Cost of placing a photo in a slot expecting another orientation. It is
+/// small, the sizes of the photos mattering more, and mostly breaks ties
+/// between slots of similar sizes.
+const ORIENTATION_COST: f32 = 0.01;
+/// Cost of placing two photos of a page in the reverse of their order
+const INVERSION_COST: f32 = 0.05;
+/// Cost of each photo skipped over, to be placed on a later page
+const SKIP_COST: f32 = 0.1;
+/// Cost of not following a change requested to reach a target page count
+const FIT_COST: f32 = 10.;
+/// Maximum number of photos a page may skip over, to group photos that are
+/// not next to each other
+pub const MAX_ORDER_WINDOW: usize = 3;
+
+/// A page of a group of images: the index of its layout, and the indices of
+/// its images in the group, in the order of the slots
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct PageLayout {
+    layout: usize,
+    images: Vec<usize>,
+}
+
+/// Change to the usual layout of an image, used to reach a target number of
+/// pages
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum Fit {
+    Normal,
+    /// The image is alone on its page
+    Alone,
+    /// The image shares a page with three others
+    Packed,
+}
+
+/// Fraction of the text area of a page covered by an image scaled to fit in
+/// a slot of a layout covering `nb_pages` pages
+fn covered_area(
+    im: &ImageInfo,
+    slot: &Slot,
+    nb_pages: usize,
+    text_dims: (f32, f32),
+) -> f32 {
+    let (photo_width, photo_height) = slot.photo_rect();
+    let slot_width = photo_width * nb_pages as f32 * text_dims.0;
+    let slot_height = photo_height * PHOTO_AREA_HEIGHT * text_dims.1;
+    let (width, height) = (im.rotated_dims.0 as f32, im.rotated_dims.1 as f32);
+    let scale = (slot_width / width).min(slot_height / height);
+    width * height * scale * scale / (text_dims.0 * text_dims.1)
+}
+
+/// Best layout of a page holding the given images, given in their order,
+/// with its cost. Spreads are only considered from a left page.
+fn best_page_layout(
+    im_group: &[ImageInfo],
+    images: &[usize],
+    left_page: bool,
+    options: LayoutOptions,
+) -> Option<(f32, PageLayout)> {
+    let mut best: Option<(f32, PageLayout)> = None;
+    let layouts = options.layouts.iter().enumerate();
+    for (layout_id, layout) in layouts.filter(|(_, l)| {
+        l.slots.len() == images.len() && (left_page || !l.spread)
+    }) {
+        // taking two pages, a spread is kept for the photos it expects
+        if layout.spread
+            && images
+                .iter()
+                .zip(&layout.slots)
+                .any(|(&i, slot)| !slot.fits(&im_group[i]))
+        {
+            continue;
+        }
+        let nb_pages = layout.nb_pages() as f32;
+        for order in (0..images.len()).permutations(images.len()) {
+            let inversions = order
+                .iter()
+                .tuple_combinations()
+                .filter(|(a, b)| a > b)
+                .count();
+            let mut cost =
+                PAGE_COST * nb_pages + INVERSION_COST * inversions as f32;
+            for (&i, slot) in order.iter().zip(&layout.slots) {
+                let im = &im_group[images[i]];
+                let area = covered_area(
+                    im,
+                    slot,
+                    layout.nb_pages(),
+                    options.text_dims,
+                );
+                cost -= area.min(MAX_PHOTO_SIZE * nb_pages);
+                if !slot.fits(im) {
+                    cost += ORIENTATION_COST;
+                }
+            }
+            if best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+                let images = order.iter().map(|&i| images[i]).collect();
+                best = Some((
+                    cost,
+                    PageLayout {
+                        layout: layout_id,
+                        images,
+                    },
+                ));
+            }
+        }
+    }
+    best
+}
+
+/// Whether two images are on the same page of the specs
+fn same_pinned_page(a: &ImageInfo, b: &ImageInfo) -> bool {
+    match (a.user_req, b.user_req) {
+        (LayoutReq::Pinned(a), LayoutReq::Pinned(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Whether two images next to each other are laid out together: heroes are
+/// alone, and the pages of the specs apart from the other images
+fn laid_out_together(a: &ImageInfo, b: &ImageInfo) -> bool {
+    match (a.user_req, b.user_req) {
+        (LayoutReq::Hero(_), _) | (_, LayoutReq::Hero(_)) => false,
+        (LayoutReq::Pinned(a), LayoutReq::Pinned(b)) => a == b,
+        (LayoutReq::Pinned(_), _) | (_, LayoutReq::Pinned(_)) => false,
+        _ => true,
+    }
+}
+
+/// How a group of images is laid out
+enum GroupLayout {
+    /// On pages chosen by the layout engine
+    Auto,
+    /// On a full-bleed page
+    Hero(HeroFit),
+    /// On a page of the specs
+    Pinned(PageLayout),
+}
+
+impl GroupLayout {
+    /// Number of pages of the group, unless it is laid out automatically
+    fn nb_pages(&self, options: LayoutOptions) -> Option<usize> {
+        match self {
+            GroupLayout::Auto => None,
+            GroupLayout::Hero(_) => Some(1),
+            GroupLayout::Pinned(page) => {
+                Some(options.layouts[page.layout].nb_pages())
+            }
+        }
+    }
+}
+
+/// Layout of a page of the specs, holding the images of `im_group` in their
+/// order. Spreads are only used when named.
+fn pinned_layout(
+    im_group: &[ImageInfo],
+    page: &PageSpec,
+    options: LayoutOptions,
+) -> Result<PageLayout, String> {
+    let names = page.images().join(", ");
+    let images: Vec<_> = (0..im_group.len()).collect();
+    match page.layout() {
+        Some(name) => {
+            let layout = options
+                .layouts
+                .iter()
+                .position(|l| l.name == name)
+                .ok_or_else(|| {
+                    format!("unknown layout {} for the page of {}", name, names)
+                })?;
+            let nb_slots = options.layouts[layout].slots.len();
+            if nb_slots != images.len() {
+                return Err(format!(
+                    "layout {} has {} slots, for the {} photos of the page \
+                     of {}",
+                    name,
+                    nb_slots,
+                    images.len(),
+                    names,
+                ));
+            }
+            Ok(PageLayout { layout, images })
+        }
+        None => best_page_layout(im_group, &images, false, options)
+            .map(|(_, layout)| layout)
+            .ok_or_else(|| {
+                format!(
+                    "no layout for the {} photos of the page of {}",
+                    images.len(),
+                    names,
+                )
+            }),
+    }
+}
+
+fn group_layout(
+    folder_spec: &FolderSpec,
+    im_group: &[ImageInfo],
+    options: LayoutOptions,
+) -> std::io::Result<GroupLayout> {
+    match im_group.first().map(|im| im.user_req) {
+        Some(LayoutReq::Hero(fit)) => Ok(GroupLayout::Hero(fit)),
+        Some(LayoutReq::Pinned(page)) => {
+            let page = &folder_spec.pages()[page];
+            pinned_layout(im_group, page, options)
+                .map(GroupLayout::Pinned)
+                .map_err(|e| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+                })
+        }
+        _ => Ok(GroupLayout::Auto),
+    }
+}
+
+/// Whether an image must be alone on its page, as requested in the specs
+fn must_be_alone(im: &ImageInfo) -> bool {
+    im.user_req == LayoutReq::OnePortrait && !is_landscape(im)
+}
+
+/// Lay out a group of images on pages, in the order of their first image.
+///
+/// The pages are chosen by dynamic programming over the ordered images, to
+/// minimize the total cost of the pages: each page gets the first image not
+/// placed yet, along with images among the following ones, skipping over at
+/// most `order_window` images. A page costs `PAGE_COST`, minus the sizes of
+/// its photos, plus the order disruptions. `first_page` is the index of the
+/// first page among the pages of photos, the first one being a right page,
+/// so that spreads start on left pages.
+fn plan_layouts(
+    im_group: &[ImageInfo],
+    fits: &[Fit],
+    first_page: usize,
+    options: LayoutOptions,
+) -> Vec<PageLayout> {
+    let nb_images = im_group.len();
+    let order_window = options.order_window.min(MAX_ORDER_WINDOW);
+    let lookahead = MAX_PER_PAGE - 1 + order_window;
+    let nb_masks = 1 << lookahead;
+    // the state is the first image not placed yet, which of the
+    // `lookahead` next images are already placed, and whether the next page
+    // is a left page
+    let state = |first: usize, mask: usize, left_page: bool| {
+        2 * (first * nb_masks + mask) + left_page as usize
+    };
+    let mut costs = vec![0.; 2 * (nb_images + 1) * nb_masks];
+    let mut choices: Vec<Option<(PageLayout, usize)>> =
+        vec![None; 2 * (nb_images + 1) * nb_masks];
+    // the same images are considered for a page under many states
+    let mut page_layouts = HashMap::new();
+    for (first, left_page) in (0..nb_images)
+        .rev()
+        .flat_map(|first| vec![(first, false), (first, true)])
+    {
+        let next_images: Vec<_> = (1..=lookahead)
+            .filter(|offset| first + offset < nb_images)
+            .collect();
+        for mask in 0..nb_masks {
+            let free: Vec<_> = next_images
+                .iter()
+                .filter(|&&offset| mask & (1 << (offset - 1)) == 0)
+                .collect();
+            let mut best: Option<(f32, PageLayout, usize)> = None;
+            let nb_others = if must_be_alone(&im_group[first]) {
+                0
+            } else {
+                MAX_PER_PAGE - 1
+            };
+            let others = std::iter::once(Vec::new()).chain(
+                (1..=nb_others).flat_map(|nb| free.iter().combinations(nb)),
+            );
+            for others in others {
+                let images: Vec<_> = std::iter::once(first)
+                    .chain(others.iter().map(|&&&offset| first + offset))
+                    .collect();
+                if images.len() > 1
+                    && images.iter().any(|&i| must_be_alone(&im_group[i]))
+                {
+                    continue;
+                }
+                let (mut cost, layout) = match page_layouts
+                    .entry((images.clone(), left_page))
+                    .or_insert_with(|| {
+                        best_page_layout(im_group, &images, left_page, options)
+                    }) {
+                    Some((cost, layout)) => (*cost, layout.clone()),
+                    None => continue,
+                };
+                // images between the first and the last ones of the
+                // page, left for later pages
+                let last = *images.last().unwrap();
+                let skipped = free
+                    .iter()
+                    .filter(|&&&offset| {
+                        first + offset < last
+                            && !images.contains(&(first + offset))
+                    })
+                    .count();
+                if skipped > order_window {
+                    continue;
+                }
+                cost += SKIP_COST * skipped as f32;
+                let unmet_fits = images
+                    .iter()
+                    .filter(|&&i| match fits[i] {
+                        Fit::Normal => false,
+                        Fit::Alone => images.len() > 1,
+                        Fit::Packed => images.len() < MAX_PER_PAGE,
+                    })
+                    .count();
+                cost += FIT_COST * unmet_fits as f32;
+                // next state: the placed images are shifted so that the
+                // mask starts after the next image not placed
+                let mut placed = mask;
+                for &i in &images[1..] {
+                    placed |= 1 << (i - first - 1);
+                }
+                let shift = (0..lookahead)
+                    .find(|bit| placed & (1 << bit) == 0)
+                    .unwrap_or(lookahead);
+                let next_first = (first + shift + 1).min(nb_images);
+                let next_mask = placed >> (shift + 1).min(lookahead);
+                let nb_pages = options.layouts[layout.layout].nb_pages();
+                let next_left_page = left_page ^ (nb_pages % 2 == 1);
+                let next = state(next_first, next_mask, next_left_page);
+                cost += costs[next];
+                if best.as_ref().is_none_or(|(c, _, _)| cost < *c) {
+                    best = Some((cost, layout, next));
+                }
+            }
+            let (cost, layout, next) = best.expect("no layout for one image");
+            costs[state(first, mask, left_page)] = cost;
+            choices[state(first, mask, left_page)] = Some((layout, next));
+        }
+    }
+    let mut layouts = Vec::new();
+    let mut current = state(0, 0, first_page % 2 == 1);
+    while let Some((layout, next)) = &choices[current] {
+        layouts.push(layout.clone());
+        current = *next;
+    }
+    layouts
+}
+
+/// Write a page of a group of images, or two for a spread
+fn write_page_layout(
+    out_folder: &Path,
+    page_id: &mut usize,
+    im_group: &[ImageInfo],
+    page: &PageLayout,
+    options: LayoutOptions,
+) -> std::io::Result<Vec<PageInfo>> {
+    let layout = &options.layouts[page.layout];
+    let images: Vec<_> = page.images.iter().map(|&i| &im_group[i]).collect();
+    if layout.spread {
+        let mut page_infos = Vec::new();
+        // pages of even ids are right pages: a spread of the specs falling
+        // on one starts after a blank page, so that its halves face each
+        // other
+        if page_id.is_multiple_of(2) {
+            log::warn!(
+                "adding a blank page before the spread of {:?}",
+                images[0].source_path,
+            );
+            page_infos.push(Page::new(page_id, out_folder).write_blank()?);
+        }
+        let left_page = Page::new(page_id, out_folder);
+        let right_page = Page::new(page_id, out_folder);
+        page_infos
+            .extend(left_page.write_spread(right_page, layout, images[0])?);
+        Ok(page_infos)
+    } else {
+        let page = Page::new(page_id, out_folder);
+        Ok(vec![page.write_layout(layout, &images)?])
+    }
+}
+
+/// Lay out a group of images on pages, returned in the order of the images
+fn layout_images(
+    out_folder: &Path,
+    page_id: &mut usize,
+    im_group: &[ImageInfo],
+    fits: &[Fit],
+    options: LayoutOptions,
+) -> std::io::Result<Vec<PageInfo>> {
+    let mut page_infos = Vec::new();
+    for page in plan_layouts(im_group, fits, *page_id, options) {
+        page_infos.extend(write_page_layout(
+            out_folder, page_id, im_group, &page, options,
+        )?);
+    }
+    Ok(page_infos)
+}
+
+/// Number of pages of the layouts of a group of images
+fn nb_pages(layouts: &[PageLayout], options: LayoutOptions) -> usize {
+    layouts
+        .iter()
+        .map(|page| options.layouts[page.layout].nb_pages())
+        .sum()
+}
+
+/// Indices of `nb` images out of `nb_images`, spread over all of them
+fn spread(nb: usize, nb_images: usize) -> impl Iterator<Item = usize> {
+    (0..nb).map(move |i| (2 * i + 1) * nb_images / (2 * nb))
+}
+
+/// Changes to the usual layouts of the images, so that the PDF has
+/// `target_pages` pages, or as close as possible. Images are either moved
+/// alone on their page or packed four per page, evenly over the album.
+/// The groups of images come with their number of pages, unless they are
+/// laid out automatically.
+fn fit_page_count(
+    groups: &[(&[ImageInfo], Option<usize>)],
+    options: LayoutOptions,
+    target_pages: usize,
+) -> (Vec<Fit>, TargetReport) {
+    let images: Vec<_> = groups.iter().flat_map(|(g, _)| g.iter()).collect();
+    // most groups keep the same fits from one try to the next: their
+    // number of pages is kept, by group, fits and parity of the first page
+    let mut group_pages = HashMap::new();
+    let mut pdf_pages = |fits: &[Fit]| {
+        let mut offset = 0;
+        let mut page_id = 0;
+        for (group, &(g, fixed_pages)) in groups.iter().enumerate() {
+            let group_fits = &fits[offset..offset + g.len()];
+            offset += g.len();
+            page_id += fixed_pages.unwrap_or_else(|| {
+                *group_pages
+                    .entry((group, group_fits.to_vec(), page_id % 2))
+                    .or_insert_with(|| {
+                        let layouts =
+                            plan_layouts(g, group_fits, page_id, options);
+                        nb_pages(&layouts, options)
+                    })
+            });
+        }
+        pdf_page_count(page_id, options.strip_inner_covers)
+    };
+    let usual_pages = pdf_pages(&vec![Fit::Normal; images.len()]);
+    let mut report = TargetReport {
+        target_pages,
+        pdf_pages: usual_pages,
+        nb_images: images.len(),
+        nb_alone: 0,
+        nb_packed: 0,
+    };
+    if usual_pages == target_pages {
+        return (vec![Fit::Normal; images.len()], report);
+    }
+    let fit = if usual_pages < target_pages {
+        Fit::Alone
+    } else {
+        Fit::Packed
+    };
+    // requests of the specs are kept
+    let adjustable: Vec<_> = (0..images.len())
+        .filter(|&i| images[i].user_req == LayoutReq::Nothing)
+        .collect();
+    let fits = |nb| {
+        let mut fits = vec![Fit::Normal; images.len()];
+        for i in spread(nb, adjustable.len()) {
+            fits[adjustable[i]] = fit;
+        }
+        fits
+    };
+    let reached = |nb_pages| match fit {
+        Fit::Alone => nb_pages >= target_pages,
+        _ => nb_pages <= target_pages,
+    };
+    // the number of pages mostly grows, or shrinks, with the number of
+    // changed images: look for the first one reaching the target, and its
+    // neighbours
+    let (mut low, mut high) = (0, adjustable.len());
+    while low < high {
+        let mid = (low + high) / 2;
+        if reached(pdf_pages(&fits(mid))) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    let distance =
+        |pages: usize| (pages as isize - target_pages as isize).abs();
+    let best = (low.saturating_sub(1)..=(low + 1).min(adjustable.len()))
+        .map(|nb| (pdf_pages(&fits(nb)), nb))
+        .min_by_key(|&(nb_pages, nb)| (distance(nb_pages), nb))
+        .unwrap();
+    report.pdf_pages = best.0;
+    match fit {
+        Fit::Alone => report.nb_alone = best.1,
+        _ => report.nb_packed = best.1,
+    }
+    (fits(best.1), report)
+}
+
+/// What was changed to the layouts to reach the page count asked for
+#[derive(Debug)]
+pub struct TargetReport {
+    pub target_pages: usize,
+    /// Number of pages of the PDF, which misses the target when the photos
+    /// cannot fill it
+    pub pdf_pages: usize,
+    pub nb_images: usize,
+    /// Number of photos moved alone on their page
+    pub nb_alone: usize,
+    /// Number of photos packed four per page
+    pub nb_packed: usize,
+}
+
+impl std::fmt::Display for TargetReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.pdf_pages != self.target_pages {
+            return write!(
+                f,
+                "cannot fit the {} photos in {} pages, the album has {} pages",
+                self.nb_images, self.target_pages, self.pdf_pages,
+            );
+        }
+        match (self.nb_alone, self.nb_packed) {
+            (0, 0) => write!(
+                f,
+                "the usual layouts already give {} pages",
+                self.pdf_pages
+            ),
+            (nb_alone, 0) => write!(
+                f,
+                "{} photos laid out alone on their page, to reach {} pages",
+                nb_alone, self.target_pages,
+            ),
+            (_, nb_packed) => write!(
+                f,
+                "{} photos laid out four per page, to reach {} pages",
+                nb_packed, self.target_pages,
+            ),
+        }
+    }
+}
+
+/// Kind of the pages added to meet the page count rules of a print shop
+#[derive(Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FillerKind {
+    #[default]
+    Blank,
+    /// Lined pages to write notes
+    Notes,
+    /// Small versions of photos of the album
+    Mosaic,
+}
+
+impl std::str::FromStr for FillerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blank" => Ok(FillerKind::Blank),
+            "notes" => Ok(FillerKind::Notes),
+            "mosaic" => Ok(FillerKind::Mosaic),
+            _ => Err(format!("unsupported filler pages: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for FillerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            FillerKind::Blank => "blank",
+            FillerKind::Notes => "notes",
+            FillerKind::Mosaic => "mosaic",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Number of pages of the PDF for `nb_pages` pages of photos. The title page
+/// and the covers are added, the back cover being on an even page. When the
+/// inner covers are stripped, only the title page and the back cover remain.
+pub fn pdf_page_count(nb_pages: usize, strip_inner_covers: bool) -> usize {
+    if strip_inner_covers {
+        nb_pages + 2
+    } else if nb_pages.is_multiple_of(2) {
+        nb_pages + 4
+    } else {
+        nb_pages + 3
+    }
+}
+
+/// What was done to meet the page count rules of the print shop
+#[derive(Debug)]
+pub struct FillerReport {
+    pub kind: FillerKind,
+    /// Number of filler pages added
+    pub nb_fillers: usize,
+    /// Number of pages of the PDF, filler pages included
+    pub pdf_pages: usize,
+    /// Rule that could not be met, if any
+    pub unmet_rule: Option<String>,
+}
+
+impl std::fmt::Display for FillerReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.unmet_rule, self.nb_fillers) {
+            (Some(rule), _) => write!(
+                f,
+                "could not meet the page count rules: {}, no page added",
+                rule
+            ),
+            (None, 0) => write!(
+                f,
+                "the {} pages of the PDF meet the page count rules",
+                self.pdf_pages
+            ),
+            (None, nb_fillers) => write!(
+                f,
+                "added {} {} filler page(s) at the end of the album, to \
+                 reach {} pages",
+                nb_fillers, self.kind, self.pdf_pages,
+            ),
+        }
+    }
+}
+
+/// Add filler pages after the pages of photos, until the PDF meets the page
+/// count rules of the print shop profile
+pub fn add_filler_pages(
+    out_folder: &Path,
+    page_infos: &mut Vec<PageInfo>,
+    folder_infos: &[FolderInfo],
+    profile: &PrintProfile,
+    strip_inner_covers: bool,
+    kind: FillerKind,
+    language: Language,
+) -> std::io::Result<FillerReport> {
+    let nb_pages = page_infos.len();
+    let pdf_pages =
+        |nb_fillers| pdf_page_count(nb_pages + nb_fillers, strip_inner_covers);
+    // adding pages past the minimum page count and a few multiples cannot
+    // help anymore
+    let max_fillers =
+        profile.min_pages.unwrap_or(0) + 2 * profile.page_multiple;
+    let nb_fillers = (0..=max_fillers)
+        .find(|&nb| profile.check_page_count(pdf_pages(nb)).is_ok());
+    let nb_fillers = match nb_fillers {
+        Some(nb_fillers) => nb_fillers,
+        None => {
+            return Ok(FillerReport {
+                kind,
+                nb_fillers: 0,
+                pdf_pages: pdf_pages(0),
+                unmet_rule: profile.check_page_count(pdf_pages(0)).err(),
+            })
+        }
+    };
+    let images: Vec<_> =
+        folder_infos.iter().flat_map(|f| &f.image_infos).collect();
+    for filler_id in 0..nb_fillers {
+        let page = Page::filler(filler_id, out_folder);
+        let page_info = match kind {
+            FillerKind::Blank => page.write_blank()?,
+            FillerKind::Notes => page.write_notes(language.notes_title())?,
+            FillerKind::Mosaic => {
+                // spread the photos of the album over the mosaics, showing
+                // some photos several times when there are only a few
+                let mosaic_size = MOSAIC_SIZE.min(images.len());
+                let nb_slots = nb_fillers * mosaic_size;
+                let mosaic: Vec<_> = (0..mosaic_size)
+                    .map(|i| {
+                        let slot = filler_id * mosaic_size + i;
+                        images[slot * images.len() / nb_slots]
+                    })
+                    .collect();
+                page.write_mosaic(&mosaic)?
+            }
+        };
+        page_infos.push(page_info);
+    }
+    Ok(FillerReport {
+        kind,
+        nb_fillers,
+        pdf_pages: pdf_pages(nb_fillers),
+        unmet_rule: None,
+    })
+}
+
+/// Options controlling how the pages are laid out
+#[derive(Copy, Clone, Debug)]
+pub struct LayoutOptions<'a> {
+    /// Start a new page with a heading for each new day of photos
+    pub day_headings: bool,
+    /// Language of the day headings
+    pub language: Language,
+    /// Width and height of the text area of the pages, in mm, which decide
+    /// of the layouts used
+    pub text_dims: (f32, f32),
+    /// Number of photos a page may skip over, to be grouped with later
+    /// ones. With 0, only photos next to each other share a page and the
+    /// album keeps their order.
+    pub order_window: usize,
+    /// Layouts among which the ones of the pages are chosen
+    pub layouts: &'a [Layout],
+    /// Sheets of the album, whose bleed box hero photos cover
+    pub page_geometry: PageGeometry,
+    /// Number of pages of the PDF to reach, by using denser or sparser
+    /// layouts
+    pub target_pages: Option<usize>,
+    /// Whether the inner covers are removed from the PDF, which changes its
+    /// number of pages
+    pub strip_inner_covers: bool,
+}
+
 pub fn write_pages(
     out_folder: &Path,
     folder_infos: &[FolderInfo],
-) -> std::io::Result<Vec<PageInfo>> {
+    options: LayoutOptions,
+) -> std::io::Result<(Vec<PageInfo>, Option<TargetReport>)> {
     let nb_images = folder_infos.iter().map(|v| v.image_infos.len()).sum();
     let mut page_infos = Vec::with_capacity(nb_images);
     let mut page_id = 0;
-    for folder_info in folder_infos {
-        let im_group = &folder_info.image_infos;
-        let nb_in_group = im_group.len();
-        let mut group_infos = Vec::with_capacity(nb_in_group);
-        let two_landscapes = im_group
-            .iter()
-            .enumerate()
-            .filter(|(_, im)| im.rotated_dims.0 >= im.rotated_dims.1)
-            .tuples();
-        let one_portrait = im_group.iter().enumerate().filter(|(_, im)| {
-            im.rotated_dims.0 < im.rotated_dims.1
-                && im.user_req == LayoutReq::OnePortrait
-        });
-
-        let mut processed = Vec::with_capacity(nb_in_group);
-        for ((page_order, im0), (im1_id, im1)) in two_landscapes {
-            let page = Page::new(&mut page_id, out_folder);
-            let page_info = page.write_two_landscapes(im0, im1)?;
-            group_infos.push((page_order, page_info));
-            processed.push(page_order);
-            processed.push(im1_id);
-        }
-        for (page_order, im) in one_portrait {
-            let page = Page::new(&mut page_id, out_folder);
-            let page_info = page.write_one_portrait(im)?;
-            group_infos.push((page_order, page_info));
-            processed.push(page_order);
-        }
-        let processed: std::collections::HashSet<_> =
-            processed.iter().collect();
-        let missing: Vec<_> = (0..nb_in_group)
-            .filter(|i| !processed.contains(i))
-            .collect();
-        let mut nb_consec = 0;
-        let mut nb_landscape = 0;
-        for (missing_id, &page_order) in missing.iter().enumerate() {
-            nb_consec += 1;
-            let im = &im_group[page_order];
-            if im.rotated_dims.0 >= im.rotated_dims.1 {
-                nb_landscape += 1;
+    let first_day = folder_infos
+        .iter()
+        .flat_map(|f| &f.image_infos)
+        .map(|im| im.date.date())
+        .min();
+    let groups: Vec<_> = folder_infos
+        .iter()
+        .flat_map(|folder_info| {
+            let im_group = &folder_info.image_infos[..];
+            // the images of a page of the specs stay together, even when
+            // they were taken on different days
+            let day_groups: Vec<_> = if options.day_headings {
+                im_group
+                    .chunk_by(|im0, im1| {
+                        im0.date.date() == im1.date.date()
+                            || same_pinned_page(im0, im1)
+                    })
+                    .collect()
+            } else {
+                vec![im_group]
+            };
+            // heroes and the pages of the specs are laid out apart,
+            // between the pages of the images around them
+            day_groups
+                .into_iter()
+                .flat_map(|g| g.chunk_by(laid_out_together))
+                .enumerate()
+                .map(move |(i, g)| (folder_info, i == 0, g))
+        })
+        .collect();
+    let group_layouts = groups
+        .iter()
+        .map(|(folder_info, _, g)| {
+            group_layout(&folder_info.folder_spec, g, options)
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let (fits, target_report) = match options.target_pages {
+        Some(target_pages) => {
+            let im_groups: Vec<_> = groups
+                .iter()
+                .zip(&group_layouts)
+                .map(|((_, _, g), layout)| (*g, layout.nb_pages(options)))
+                .collect();
+            let (fits, report) =
+                fit_page_count(&im_groups, options, target_pages);
+            (fits, Some(report))
+        }
+        None => (vec![Fit::Normal; nb_images], None),
+    };
+    let mut prev_day = None;
+    let mut offset = 0;
+    let mut first_page = true;
+    let mut day_heading = None;
+    for ((folder_info, first_group, day_group), group_layout) in
+        groups.into_iter().zip(group_layouts)
+    {
+        first_page |= first_group;
+        let group_infos = match group_layout {
+            GroupLayout::Hero(fit) => {
+                let bleed_box = options.page_geometry.bleed_box();
+                vec![Page::new(&mut page_id, out_folder).write_hero(
+                    &day_group[0],
+                    fit,
+                    bleed_box,
+                )?]
             }
-            let last = missing_id == missing.len() - 1;
-            if nb_landscape == 1 && nb_consec == 3 {
-                let page = Page::new(&mut page_id, out_folder);
-                let page_info = page.write_two_portraits_one_landscape(
-                    &im_group[missing[missing_id - 2]],
-                    &im_group[missing[missing_id - 1]],
-                    &im_group[page_order],
-                )?;
-                group_infos.push((page_order, page_info));
-                nb_consec = 0;
-                nb_landscape = 0;
-            } else if nb_consec == 4 {
-                // there could be one landscape here, but we accept to have
-                // it small.
-                let page = Page::new(&mut page_id, out_folder);
-                let page_info = page.write_four_portraits(
-                    &im_group[missing[missing_id - 3]],
-                    &im_group[missing[missing_id - 2]],
-                    &im_group[missing[missing_id - 1]],
-                    &im_group[page_order],
-                )?;
-                group_infos.push((page_order, page_info));
-                nb_consec = 0;
-                nb_landscape = 0;
-            } else if nb_consec == 1 && last {
-                let page = Page::new(&mut page_id, out_folder);
-                let page_info =
-                    page.write_one_portrait(&im_group[page_order])?;
-                group_infos.push((page_order, page_info));
-                page_id += 1;
-                nb_consec = 0;
-                nb_landscape = 0;
-            } else if nb_consec == 2 && last {
-                let page = Page::new(&mut page_id, out_folder);
-                let page_info = page.write_two_landscapes(
-                    &im_group[missing[missing_id - 1]],
-                    &im_group[page_order],
-                )?;
-                group_infos.push((page_order, page_info));
-                nb_consec = 0;
-                nb_landscape = 0;
-            } else if nb_consec == 3 && last {
-                let page = Page::new(&mut page_id, out_folder);
-                let page_info = page.write_two_portraits_one_landscape(
-                    &im_group[missing[missing_id - 2]],
-                    &im_group[missing[missing_id - 1]],
-                    &im_group[page_order],
-                )?;
-                group_infos.push((page_order, page_info));
-                nb_consec = 0;
-                nb_landscape = 0;
-            } else if last {
-                unreachable!()
+            GroupLayout::Pinned(page) => write_page_layout(
+                out_folder,
+                &mut page_id,
+                day_group,
+                &page,
+                options,
+            )?,
+            GroupLayout::Auto => layout_images(
+                out_folder,
+                &mut page_id,
+                day_group,
+                &fits[offset..offset + day_group.len()],
+                options,
+            )?,
+        };
+        offset += day_group.len();
+        let day = day_group.first().map(|im| im.date.date());
+        if let (Some(day), Some(first_day)) = (day, first_day) {
+            if options.day_headings && Some(day) != prev_day {
+                let day_number = (day - first_day).num_days() + 1;
+                day_heading =
+                    Some(options.language.format_day_heading(day_number, day));
             }
-            // no terminal else as that is the case where we want to loop
         }
-
-        group_infos.sort_by_key(|(id, _)| *id);
-        for (page_number, (_, page_info)) in group_infos.iter().enumerate() {
-            let title = if page_number == 0 {
+        prev_day = day.or(prev_day);
+        // hero and blank pages have no headings, which go to the next page
+        for page_info in &group_infos {
+            if page_info.kind == PageKind::Hero
+                || page_info.kind == PageKind::Filler
+            {
+                continue;
+            }
+            let title = if first_page {
                 folder_info.folder_spec.section_title()
             } else {
                 None
             };
-            set_section_title(page_info, title)?;
+            set_page_headers(page_info, title, day_heading.take().as_deref())?;
+            first_page = false;
         }
-        page_infos.extend(group_infos.drain(..).map(|(_, info)| info));
+        page_infos.extend(group_infos);
+    }
+    Ok((page_infos, target_report))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use chrono::NaiveDate;
+
+    use image::GenericImageView;
+
+    use super::{
+        add_filler_pages, fit_page_count, layout_images, nb_pages,
+        pdf_page_count, plan_layouts, write_pages, FillerKind, Fit,
+        LayoutOptions, PageLayout,
+    };
+    use crate::latex::TexText;
+    use crate::layouts::{builtin_layouts, parse_layouts, Layout};
+    use crate::locale::Language;
+    use crate::page_format::PageGeometry;
+    use crate::profile::PrintProfile;
+    use crate::specs::FolderSpec;
+    use crate::specs::HeroFit;
+    use crate::{FolderInfo, ImageInfo, LayoutReq, PageKind};
+
+    fn image(out_folder: &Path, name: &str, dims: (u32, u32)) -> ImageInfo {
+        ImageInfo {
+            path: out_folder.join("images").join(name),
+            source_path: Path::new("src").join(name),
+            resize_dims: dims,
+            rotated_dims: dims,
+            date: NaiveDate::from_ymd(2016, 8, 9).and_hms(12, 0, 0),
+            caption: None,
+            user_req: LayoutReq::Nothing,
+        }
+    }
+
+    const PORTRAIT_TEXT: (f32, f32) = (180., 267.);
+    const LANDSCAPE_TEXT: (f32, f32) = (267., 180.);
+    const SQUARE_TEXT: (f32, f32) = (180., 180.);
+
+    fn options(
+        layouts: &[Layout],
+        text_dims: (f32, f32),
+        order_window: usize,
+    ) -> LayoutOptions<'_> {
+        LayoutOptions {
+            day_headings: false,
+            language: Language::English,
+            text_dims,
+            order_window,
+            layouts,
+            page_geometry: PageGeometry {
+                trim_dims: (210., 297.),
+                bleed: 0.,
+                crop_marks: false,
+            },
+            target_pages: None,
+            strip_inner_covers: false,
+        }
+    }
+
+    /// Images whose shape is given by the start of their names: panoramas,
+    /// wide landscapes, landscapes or portraits
+    fn images(names: &[&str]) -> Vec<ImageInfo> {
+        let out_folder = Path::new("out");
+        names
+            .iter()
+            .map(|name| {
+                let dims = if name.starts_with("pano") {
+                    (1200, 300)
+                } else if name.starts_with('w') {
+                    (880, 400)
+                } else if name.starts_with('l') {
+                    (600, 400)
+                } else {
+                    (400, 600)
+                };
+                image(out_folder, name, dims)
+            })
+            .collect()
+    }
+
+    fn plan(names: &[&str], options: LayoutOptions) -> Vec<PageLayout> {
+        let fits = vec![Fit::Normal; names.len()];
+        plan_layouts(&images(names), &fits, 0, options)
+    }
+
+    fn page(layout: &str, images: &[usize]) -> PageLayout {
+        PageLayout {
+            layout: builtin_layouts()
+                .iter()
+                .position(|l| l.name == layout)
+                .unwrap(),
+            images: images.to_vec(),
+        }
+    }
+
+    #[test]
+    fn layouts_follow_page_orientation() {
+        let layouts = builtin_layouts();
+        let names = ["p0", "l1", "p2", "l3"];
+        assert_eq!(
+            plan(&names, options(&layouts, PORTRAIT_TEXT, 0)),
+            [
+                page("two_landscapes", &[0, 1]),
+                page("two_landscapes", &[2, 3]),
+            ],
+        );
+        assert_eq!(
+            plan(&names, options(&layouts, LANDSCAPE_TEXT, 0)),
+            [
+                page("two_portraits", &[0, 1]),
+                page("two_portraits", &[2, 3]),
+            ],
+        );
+        assert_eq!(
+            plan(&names, options(&layouts, SQUARE_TEXT, 1)),
+            [
+                page("two_portraits", &[0, 2]),
+                page("two_landscapes", &[1, 3]),
+            ],
+        );
+    }
+
+    #[test]
+    fn order_window_keeps_photo_order() {
+        let layouts = builtin_layouts();
+        let names = ["p0", "l1", "p2", "l3", "p4", "l5", "p6", "l7"];
+        for text_dims in &[PORTRAIT_TEXT, LANDSCAPE_TEXT, SQUARE_TEXT] {
+            let mut images: Vec<_> =
+                plan(&names, options(&layouts, *text_dims, 0))
+                    .into_iter()
+                    .map(|page| {
+                        let mut images = page.images;
+                        images.sort_unstable();
+                        images
+                    })
+                    .collect();
+            let in_order: Vec<_> = images.concat();
+            assert_eq!(in_order, (0..names.len()).collect::<Vec<_>>());
+            images.retain(|images| images.len() > 1);
+            assert!(!images.is_empty());
+        }
+        // with a window, a page may skip over a photo
+        let skipping = |page: &PageLayout| {
+            page.images.iter().max().unwrap()
+                - page.images.iter().min().unwrap()
+                >= page.images.len()
+        };
+        assert!(plan(&names, options(&layouts, SQUARE_TEXT, 1))
+            .iter()
+            .any(skipping));
+        assert!(!plan(&names, options(&layouts, SQUARE_TEXT, 0))
+            .iter()
+            .any(skipping));
+    }
+
+    #[test]
+    fn user_layouts_are_chosen() {
+        let mut layouts = builtin_layouts();
+        layouts.extend(
+            parse_layouts(
+                r#"[{"name": "three_rows", "slots": [
+                    {"name": "top", "rect": [0, 0, 1, 0.3333]},
+                    {"name": "middle", "rect": [0, 0.3333, 1, 0.3333]},
+                    {"name": "bottom", "rect": [0, 0.6667, 1, 0.3333]}
+                ]}]"#,
+            )
+            .unwrap(),
+        );
+        let three_rows = PageLayout {
+            layout: layouts.len() - 1,
+            images: vec![0, 1, 2],
+        };
+        assert_eq!(
+            plan(&["w0", "w1", "w2"], options(&layouts, PORTRAIT_TEXT, 0)),
+            [three_rows],
+        );
+        // the rows are too low for portraits
+        let portraits =
+            plan(&["p0", "p1", "p2"], options(&layouts, PORTRAIT_TEXT, 0));
+        assert!(portraits.iter().all(|p| p.layout != layouts.len() - 1));
+    }
+
+    #[test]
+    fn panorama_layouts() {
+        let layouts = builtin_layouts();
+        let options = options(&layouts, PORTRAIT_TEXT, 0);
+        assert_eq!(
+            plan(&["pano0", "pano1", "pano2"], options),
+            [page("panorama_stack", &[0, 1, 2])],
+        );
+        assert_eq!(
+            plan(&["pano0", "p1", "p2"], options),
+            [page("panorama_two_portraits", &[0, 1, 2])],
+        );
+        // a panorama among landscapes covers two facing pages, the first
+        // page of photos being a right page
+        let names = ["l0", "l1", "pano2", "l3", "l4"];
+        assert_eq!(
+            plan(&names, options),
+            [
+                page("two_landscapes", &[0, 1]),
+                page("panorama_spread", &[2]),
+                page("two_landscapes", &[3, 4]),
+            ],
+        );
+        let fits = vec![Fit::Normal; names.len()];
+        let from_left_page = plan_layouts(&images(&names), &fits, 1, options);
+        let mut page_id = 1;
+        for page in &from_left_page {
+            let layout = &layouts[page.layout];
+            assert!(!layout.spread || page_id % 2 == 1);
+            page_id += layout.nb_pages();
+        }
+    }
+
+    #[test]
+    fn layouts_keep_photos_large() {
+        let layouts = builtin_layouts();
+        // a landscape after three portraits is not shrunk on a page of four
+        assert_eq!(
+            plan(
+                &["p0", "p1", "p2", "l3"],
+                options(&layouts, PORTRAIT_TEXT, 0)
+            ),
+            [
+                page("one", &[0]),
+                page("two_portraits_one_landscape", &[1, 2, 3]),
+            ],
+        );
+        // the landscape goes to the wide slot
+        assert_eq!(
+            plan(&["l0", "p1", "p2"], options(&layouts, PORTRAIT_TEXT, 0)),
+            [page("two_portraits_one_landscape", &[1, 2, 0])],
+        );
+        let portraits = ["p0", "p1", "p2", "p3", "p4", "p5", "p6", "p7"];
+        assert_eq!(
+            plan(&portraits, options(&layouts, PORTRAIT_TEXT, 0)),
+            [page("four", &[0, 1, 2, 3]), page("four", &[4, 5, 6, 7]),],
+        );
+        let landscapes = ["l0", "l1", "l2", "l3"];
+        assert_eq!(
+            plan(&landscapes, options(&layouts, PORTRAIT_TEXT, 0)),
+            [
+                page("two_landscapes", &[0, 1]),
+                page("two_landscapes", &[2, 3]),
+            ],
+        );
+    }
+
+    #[test]
+    fn heroes_keep_their_place() {
+        let layouts = builtin_layouts();
+        let out_folder = std::env::temp_dir().join("phototex_heroes");
+        let mut image_infos: Vec<_> = (0..6)
+            .map(|i| image(&out_folder, &format!("{}.jpg", i), (600, 400)))
+            .collect();
+        image_infos[0].user_req = LayoutReq::Hero(HeroFit::Crop);
+        image_infos[3].user_req = LayoutReq::Hero(HeroFit::Rotate);
+        std::fs::create_dir_all(out_folder.join("images")).unwrap();
+        for &i in &[0, 3] {
+            image::RgbImage::new(600, 400)
+                .save(&image_infos[i].path)
+                .unwrap();
+        }
+        let mut folder_spec = FolderSpec::empty();
+        folder_spec.set_default_title(TexText::plain("Heroes"));
+        let folder_infos = vec![FolderInfo {
+            folder_spec,
+            image_infos,
+        }];
+        let page_infos = write_pages(
+            &out_folder,
+            &folder_infos,
+            options(&layouts, PORTRAIT_TEXT, 0),
+        )
+        .unwrap()
+        .0;
+        let kinds: Vec<_> = page_infos.iter().map(|p| &p.kind).collect();
+        let two_landscapes = PageKind::Layout("two_landscapes".to_string());
+        assert_eq!(
+            kinds,
+            [
+                &PageKind::Hero,
+                &two_landscapes,
+                &PageKind::Hero,
+                &two_landscapes
+            ],
+        );
+        // the title goes to the first page with headings
+        let page_text = std::fs::read_to_string(&page_infos[1].path).unwrap();
+        assert!(page_text.contains("Heroes"));
+        // the landscapes fit in the width of the page without cropping, the
+        // second one once turned
+        let images = out_folder.join("images");
+        let dims = |name| image::open(images.join(name)).unwrap().dimensions();
+        assert_eq!(dims("derived/0_hero.jpg"), (600, 400));
+        assert_eq!(dims("derived/3_hero.jpg"), (400, 600));
+        std::fs::remove_dir_all(&out_folder).unwrap();
+    }
+
+    #[test]
+    fn pinned_pages() {
+        let layouts = builtin_layouts();
+        let options = options(&layouts, PORTRAIT_TEXT, 0);
+        let out_folder = std::env::temp_dir().join("phototex_pinned");
+        let folder_info = |pages: &str, pinned: &[usize]| {
+            let mut image_infos: Vec<_> = (0..5)
+                .map(|i| image(&out_folder, &format!("{}.jpg", i), (600, 400)))
+                .collect();
+            for &i in pinned {
+                image_infos[i].user_req = LayoutReq::Pinned(0);
+            }
+            vec![FolderInfo {
+                folder_spec: serde_json::from_str(pages).unwrap(),
+                image_infos,
+            }]
+        };
+        let kinds_with = |folder_infos: &[FolderInfo], options| {
+            write_pages(&out_folder, folder_infos, options).map(|(pages, _)| {
+                pages
+                    .into_iter()
+                    .map(|p| match p.kind {
+                        PageKind::Layout(name) => name,
+                        _ => panic!("not a page of photos"),
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+        let kinds =
+            |folder_infos: &[FolderInfo]| kinds_with(folder_infos, options);
+        // a page of two landscapes, chosen in the specs, is kept apart
+        let pinned = folder_info(
+            r#"{"pages": [{"images": ["1.jpg", "2.jpg"],
+                            "layout": "two_portraits"}]}"#,
+            &[1, 2],
+        );
+        assert_eq!(
+            kinds(&pinned).unwrap(),
+            ["one", "two_portraits", "two_landscapes"],
+        );
+        let pinned = folder_info(r#"{"pages": [["1.jpg"]]}"#, &[1]);
+        assert_eq!(
+            kinds(&pinned).unwrap(),
+            ["one", "one", "one", "two_landscapes"],
+        );
+        // pages no layout can hold
+        let error = |pages, pinned| {
+            kinds(&folder_info(pages, pinned)).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error(
+                r#"{"pages": [["0.jpg", "1.jpg", "2.jpg", "3.jpg", "4.jpg"]]}"#,
+                &[0, 1, 2, 3, 4]
+            ),
+            "no layout for the 5 photos of the page of 0.jpg, 1.jpg, 2.jpg, \
+             3.jpg, 4.jpg",
+        );
+        assert_eq!(
+            error(
+                r#"{"pages": [{"images": ["0.jpg"], "layout": "four"}]}"#,
+                &[0]
+            ),
+            "layout four has 4 slots, for the 1 photos of the page of 0.jpg",
+        );
+        assert_eq!(
+            error(
+                r#"{"pages": [{"images": ["0.jpg"], "layout": "big"}]}"#,
+                &[0]
+            ),
+            "unknown layout big for the page of 0.jpg",
+        );
+        // a page over two days is not split by the day headings
+        let mut pinned = folder_info(
+            r#"{"pages": [{"images": ["1.jpg", "2.jpg"],
+                            "layout": "two_landscapes"}]}"#,
+            &[1, 2],
+        );
+        for im in &mut pinned[0].image_infos[2..] {
+            im.date += chrono::Duration::days(1);
+        }
+        let day_options = LayoutOptions {
+            day_headings: true,
+            ..options
+        };
+        assert_eq!(
+            kinds_with(&pinned, day_options).unwrap(),
+            ["one", "two_landscapes", "two_landscapes"],
+        );
+        // the halves of a spread face each other
+        std::fs::create_dir_all(out_folder.join("images")).unwrap();
+        let spread_kinds = |pinned: usize| {
+            let folder_infos = folder_info(
+                &format!(
+                    r#"{{"pages": [{{"images": ["{}.jpg"],
+                                     "layout": "panorama_spread"}}]}}"#,
+                    pinned,
+                ),
+                &[pinned],
+            );
+            let im = &folder_infos[0].image_infos[pinned];
+            image::RgbImage::new(600, 400).save(&im.path).unwrap();
+            write_pages(&out_folder, &folder_infos, options)
+                .unwrap()
+                .0
+                .into_iter()
+                .map(|p| p.kind)
+                .take(3)
+                .collect::<Vec<_>>()
+        };
+        let layout = |name: &str| PageKind::Layout(name.to_string());
+        assert_eq!(
+            spread_kinds(1),
+            [
+                layout("one"),
+                layout("panorama_spread"),
+                layout("panorama_spread")
+            ],
+        );
+        assert_eq!(
+            spread_kinds(0),
+            [
+                PageKind::Filler,
+                layout("panorama_spread"),
+                layout("panorama_spread")
+            ],
+        );
+        std::fs::remove_dir_all(&out_folder).unwrap();
+    }
+
+    #[test]
+    fn filler_pages_meet_page_count_rules() {
+        let layouts = builtin_layouts();
+        assert_eq!(pdf_page_count(5, false), 8);
+        assert_eq!(pdf_page_count(6, false), 10);
+        assert_eq!(pdf_page_count(5, true), 7);
+
+        let out_folder = std::env::temp_dir().join("phototex_fillers");
+        let folder_infos = vec![FolderInfo {
+            folder_spec: FolderSpec::empty(),
+            image_infos: (0..10)
+                .map(|i| image(&out_folder, &format!("{}.jpg", i), (600, 400)))
+                .collect(),
+        }];
+        let mut profile = PrintProfile::default();
+        profile.page_multiple = 4;
+        profile.min_pages = Some(12);
+        let mut page_infos = layout_images(
+            &out_folder,
+            &mut 0,
+            &folder_infos[0].image_infos,
+            &[Fit::Normal; 10],
+            options(&layouts, PORTRAIT_TEXT, 0),
+        )
+        .unwrap();
+        assert_eq!(page_infos.len(), 5);
+        let report = add_filler_pages(
+            &out_folder,
+            &mut page_infos,
+            &folder_infos,
+            &profile,
+            true,
+            FillerKind::Mosaic,
+            Language::English,
+        )
+        .unwrap();
+        assert_eq!((report.nb_fillers, report.pdf_pages), (5, 12));
+        assert_eq!(page_infos.len(), 10);
+        assert!(page_infos[5..].iter().all(|p| p.kind == PageKind::Filler));
+
+        profile.max_pages = Some(8);
+        let report = add_filler_pages(
+            &out_folder,
+            &mut page_infos,
+            &folder_infos,
+            &profile,
+            false,
+            FillerKind::Blank,
+            Language::English,
+        )
+        .unwrap();
+        assert_eq!(report.nb_fillers, 0);
+        assert!(report.unmet_rule.is_some());
+        assert_eq!(page_infos.len(), 10);
+        std::fs::remove_dir_all(&out_folder).unwrap();
+    }
+
+    #[test]
+    fn fit_target_page_count() {
+        let layouts = builtin_layouts();
+        let out_folder = Path::new("out");
+        let im_group: Vec<_> = (0..12)
+            .map(|i| image(out_folder, &format!("{}.jpg", i), (600, 400)))
+            .collect();
+        let options = options(&layouts, PORTRAIT_TEXT, 0);
+        let pdf_pages = |target_pages| {
+            let groups = [(&im_group[..], None)];
+            let (fits, _) = fit_page_count(&groups, options, target_pages);
+            let layouts = plan_layouts(&im_group, &fits, 0, options);
+            pdf_page_count(nb_pages(&layouts, options), false)
+        };
+        // six pages of two landscapes, the title page and the covers
+        assert_eq!(pdf_pages(10), 10);
+        assert_eq!(pdf_pages(14), 14);
+        assert_eq!(pdf_pages(16), 16);
+        assert_eq!(pdf_pages(6), 6);
+        // the photos cannot fill more than twelve pages, nor less than three
+        assert_eq!(pdf_pages(30), 16);
+        assert_eq!(pdf_pages(4), 6);
+        let groups = [(&im_group[..], None)];
+        let (_, report) = fit_page_count(&groups, options, 30);
+        assert_eq!(report.pdf_pages, 16);
+        assert!(report.nb_alone > 0 && report.nb_packed == 0);
+        let (_, report) = fit_page_count(&groups, options, 6);
+        assert_eq!(report.pdf_pages, 6);
+        assert!(report.nb_alone == 0 && report.nb_packed > 0);
     }
-    Ok(page_infos)
 }
@@ -0,0 +1,212 @@
+//! Self-contained copies of the generated LaTeX project, that can be built on
+//! another machine
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{tex_path, FolderInfo, PageInfo};
+
+/// A file of the bundle
+#[derive(Serialize, Debug)]
+struct BundleFile {
+    /// Path inside the bundle, with '/' separators
+    path: String,
+    /// Original image a resized copy was made from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<PathBuf>,
+    #[serde(skip)]
+    out_path: PathBuf,
+}
+
+/// Description of the bundle contents, written as `manifest.json`
+#[derive(Serialize, Debug)]
+struct Manifest {
+    toplevel: String,
+    files: Vec<BundleFile>,
+}
+
+impl Manifest {
+    fn new(
+        out_folder: &Path,
+        top_file_name: &str,
+        page_infos: &[PageInfo],
+        folder_infos: &[FolderInfo],
+    ) -> std::io::Result<Manifest> {
+        let mut files = Vec::new();
+        for name in &[top_file_name, "Makefile"] {
+            files.push(BundleFile {
+                path: name.to_string(),
+                source: None,
+                out_path: out_folder.join(name),
+            });
+        }
+        for page in page_infos {
+            for path in std::iter::once(&page.path).chain(&page.extra_files) {
+                files.push(BundleFile {
+                    path: tex_path(path, out_folder)?,
+                    source: None,
+                    out_path: path.clone(),
+                });
+            }
+        }
+        for im in folder_infos.iter().flat_map(|f| &f.image_infos) {
+            files.push(BundleFile {
+                path: tex_path(&im.path, out_folder)?,
+                source: Some(im.source_path.clone()),
+                out_path: im.path.clone(),
+            });
+        }
+        Ok(Manifest {
+            toplevel: top_file_name.to_string(),
+            files,
+        })
+    }
+
+    fn to_json(&self) -> std::io::Result<String> {
+        serde_json::to_string_pretty(self).map_err(std::io::Error::from)
+    }
+}
+
+fn write_bundle_dir(
+    bundle_path: &Path,
+    manifest: &Manifest,
+) -> std::io::Result<()> {
+    for file in &manifest.files {
+        let dest = bundle_path.join(&file.path);
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&file.out_path, &dest)?;
+    }
+    let f = File::create(bundle_path.join("manifest.json"))?;
+    let mut writer = BufWriter::new(f);
+    writeln!(writer, "{}", manifest.to_json()?)?;
+    Ok(())
+}
+
+fn write_bundle_zip(
+    bundle_path: &Path,
+    manifest: &Manifest,
+) -> std::io::Result<()> {
+    use zip::write::FileOptions;
+    use zip::CompressionMethod;
+
+    let f = File::create(bundle_path)?;
+    let mut zip = zip::ZipWriter::new(BufWriter::new(f));
+    let text_options =
+        FileOptions::default().compression_method(CompressionMethod::Deflated);
+    // jpeg images would not get any smaller
+    let image_options =
+        FileOptions::default().compression_method(CompressionMethod::Stored);
+    for file in &manifest.files {
+        let options = if file.path.ends_with(".jpg") {
+            image_options
+        } else {
+            text_options
+        };
+        zip.start_file(file.path.as_str(), options)?;
+        std::io::copy(&mut File::open(&file.out_path)?, &mut zip)?;
+    }
+    zip.start_file("manifest.json", text_options)?;
+    writeln!(zip, "{}", manifest.to_json()?)?;
+    zip.finish()?;
+    Ok(())
+}
+
+/// Copy the LaTeX files, the resized images and the Makefile of the output
+/// folder into a bundle, along with a manifest. The bundle is a zip archive
+/// if `bundle_path` ends with `.zip`, and a directory otherwise.
+pub fn write_bundle(
+    out_folder: &Path,
+    bundle_path: &Path,
+    top_file_name: &str,
+    page_infos: &[PageInfo],
+    folder_infos: &[FolderInfo],
+) -> std::io::Result<()> {
+    let manifest =
+        Manifest::new(out_folder, top_file_name, page_infos, folder_infos)?;
+    let is_zip = bundle_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    if is_zip {
+        write_bundle_zip(bundle_path, &manifest)
+    } else {
+        write_bundle_dir(bundle_path, &manifest)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::layouts::builtin_layouts;
+    use crate::pages::Page;
+    use crate::specs::{FolderSpec, HeroFit};
+    use crate::{FolderInfo, ImageInfo, LayoutReq};
+
+    #[test]
+    fn bundle_includes_page_images() {
+        let out_folder = std::env::temp_dir().join("phototex_bundle");
+        let bundle_path = std::env::temp_dir().join("phototex_bundle_copy");
+        let images = out_folder.join("images/section_00");
+        std::fs::create_dir_all(&images).unwrap();
+        for name in &["photobook.tex", "Makefile"] {
+            std::fs::write(out_folder.join(name), "").unwrap();
+        }
+        let image = |name: &str, dims: (u32, u32)| {
+            let path = images.join(name);
+            image::RgbImage::new(dims.0, dims.1).save(&path).unwrap();
+            ImageInfo {
+                path,
+                source_path: PathBuf::from("source").join(name),
+                resize_dims: dims,
+                rotated_dims: dims,
+                date: "2016-08-09T10:00:00".parse().unwrap(),
+                caption: None,
+                user_req: LayoutReq::Nothing,
+            }
+        };
+        let hero = image("hero.jpg", (600, 400));
+        let panorama = image("panorama.jpg", (1200, 300));
+        let spread = builtin_layouts()
+            .into_iter()
+            .find(|layout| layout.spread)
+            .unwrap();
+        let mut page_id = 0;
+        let mut page_infos = vec![Page::new(&mut page_id, &out_folder)
+            .write_hero(&hero, HeroFit::Crop, (0., 0., 210., 297.))
+            .unwrap()];
+        let left_page = Page::new(&mut page_id, &out_folder);
+        let right_page = Page::new(&mut page_id, &out_folder);
+        page_infos.extend(
+            left_page
+                .write_spread(right_page, &spread, &panorama)
+                .unwrap(),
+        );
+        let folder_infos = vec![FolderInfo {
+            folder_spec: FolderSpec::empty(),
+            image_infos: vec![hero, panorama],
+        }];
+        super::write_bundle(
+            &out_folder,
+            &bundle_path,
+            "photobook.tex",
+            &page_infos,
+            &folder_infos,
+        )
+        .unwrap();
+        for name in &[
+            "page000/page.tex",
+            "page002/page.tex",
+            "images/section_00/derived/hero_hero.jpg",
+            "images/section_00/derived/panorama_left.jpg",
+            "images/section_00/derived/panorama_right.jpg",
+        ] {
+            assert!(bundle_path.join(name).is_file(), "{} is missing", name);
+        }
+        std::fs::remove_dir_all(&out_folder).unwrap();
+        std::fs::remove_dir_all(&bundle_path).unwrap();
+    }
+}
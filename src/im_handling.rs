@@ -1,39 +1,52 @@
+//! Image utility functions
+use std::collections::BTreeMap;
 use std::error::Error;
-///! Image utility functions
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use glob::glob;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
 use image::{ImageDecoder, ImageOutputFormat, ImageResult};
 use rayon::prelude::*;
 
-use crate::specs::FolderSpec;
+use crate::latex::TexText;
+use crate::locale::Language;
+use crate::profile::ColorMode;
+use crate::resize_cache::{self, CacheEntry, ResizeCache};
+use crate::sections::{self, AutoSections};
+use crate::specs::{FolderSpec, HeroFit, MetadataFilter, RootSpec, SortOrder};
+use crate::xmp::XmpMeta;
 use crate::{
-    FolderInfo, ImageInfo, LayoutReq, Orientation, SourceFolderInfo,
+    tex_path, FolderInfo, ImageInfo, LayoutReq, Orientation, SourceFolderInfo,
     SourceImageInfo,
 };
 
-fn image_exif_orientation(path: &Path) -> Orientation {
-    let thumbnail = false;
+fn read_exif(path: &Path) -> Option<exif::Reader> {
     let mut fin = std::fs::File::open(path).map(std::io::BufReader::new).ok();
-    let reader = fin
-        .as_mut()
+    fin.as_mut()
         .ok_or(exif::Error::BlankValue("dummy"))
         .and_then(exif::Reader::new)
-        .ok();
-    let orientation = reader
-        .as_ref()
-        .and_then(|r| r.get_field(exif::Tag::Orientation, thumbnail));
+        .ok()
+}
+
+fn image_exif_orientation(
+    reader: Option<&exif::Reader>,
+    path: &Path,
+) -> Orientation {
+    let thumbnail = false;
+    let orientation =
+        reader.and_then(|r| r.get_field(exif::Tag::Orientation, thumbnail));
     if let Some(orientation) = orientation {
         match &orientation.value {
             exif::Value::Short(vals) => {
                 if vals.len() == 1 {
-                    match vals[0] {
-                        1 => Orientation::Keep,
-                        3 => Orientation::Rotate180,
-                        6 => Orientation::Rotate90,
-                        8 => Orientation::Rotate270,
-                        _ => Orientation::Flipped,
+                    let orientation = Orientation::from_exif(vals[0].into());
+                    if orientation == Orientation::Unknown {
+                        log::info!(
+                            "Invalid orientation {} for {:?}",
+                            vals[0],
+                            path
+                        );
                     }
+                    orientation
                 } else {
                     Orientation::Unknown
                 }
@@ -49,6 +62,109 @@ fn image_exif_orientation(path: &Path) -> Orientation {
     }
 }
 
+fn exif_date_field(
+    reader: &exif::Reader,
+    tag: exif::Tag,
+) -> Option<NaiveDateTime> {
+    let thumbnail = false;
+    let field = reader.get_field(tag, thumbnail)?;
+    let ascii = match &field.value {
+        exif::Value::Ascii(vals) => vals.first()?,
+        _ => return None,
+    };
+    let dt = exif::DateTime::from_ascii(ascii).ok()?;
+    NaiveDate::from_ymd_opt(dt.year.into(), dt.month.into(), dt.day.into())?
+        .and_hms_opt(dt.hour.into(), dt.minute.into(), dt.second.into())
+}
+
+fn exif_ascii_field(reader: &exif::Reader, tag: exif::Tag) -> Option<String> {
+    let thumbnail = false;
+    let field = reader.get_field(tag, thumbnail)?;
+    match &field.value {
+        exif::Value::Ascii(vals) => {
+            let val = String::from_utf8_lossy(vals.first()?);
+            let val =
+                val.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            if val.is_empty() {
+                None
+            } else {
+                Some(val.to_string())
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Camera name built from the EXIF Make and Model fields. Many vendors
+/// already repeat the make in the model, in which case it is not doubled.
+fn image_exif_camera(reader: Option<&exif::Reader>) -> Option<String> {
+    let reader = reader?;
+    let make = exif_ascii_field(reader, exif::Tag::Make);
+    let model = exif_ascii_field(reader, exif::Tag::Model);
+    match (make, model) {
+        (Some(make), Some(model)) => {
+            if model.to_lowercase().starts_with(&make.to_lowercase()) {
+                Some(model)
+            } else {
+                Some(format!("{} {}", make, model))
+            }
+        }
+        (make, model) => make.or(model),
+    }
+}
+
+/// Descriptions written by some cameras in every image
+const PLACEHOLDER_DESCRIPTIONS: &[&str] =
+    &["OLYMPUS DIGITAL CAMERA", "SONY DSC", "DCIM", "Default"];
+
+/// Caption of an image, from its sidecar text file (`IMG_1234.txt` or
+/// `IMG_1234.jpg.txt`), or else from its XMP or EXIF description
+fn image_caption(
+    reader: Option<&exif::Reader>,
+    xmp: Option<&XmpMeta>,
+    path: &Path,
+) -> Option<TexText> {
+    let mut long_name = path.as_os_str().to_owned();
+    long_name.push(".txt");
+    let sidecars = [path.with_extension("txt"), PathBuf::from(long_name)];
+    for sidecar in sidecars.iter().filter(|p| p.is_file()) {
+        match std::fs::read_to_string(sidecar) {
+            Ok(caption) => return Some(TexText::plain(caption.trim())),
+            Err(e) => log::warn!("Could not read caption {:?}: {}", sidecar, e),
+        }
+    }
+    xmp.and_then(|meta| meta.description.clone())
+        .or_else(|| {
+            reader
+                .and_then(|r| exif_ascii_field(r, exif::Tag::ImageDescription))
+        })
+        .filter(|desc| !PLACEHOLDER_DESCRIPTIONS.contains(&desc.as_str()))
+        .map(TexText::Plain)
+}
+
+/// Creation date of an image: EXIF DateTimeOriginal, then EXIF DateTime,
+/// then the modification time of the file.
+fn image_creation_date(
+    reader: Option<&exif::Reader>,
+    path: &Path,
+) -> NaiveDateTime {
+    let exif_date = reader.and_then(|r| {
+        exif_date_field(r, exif::Tag::DateTimeOriginal)
+            .or_else(|| exif_date_field(r, exif::Tag::DateTime))
+    });
+    if let Some(date) = exif_date {
+        return date;
+    }
+    log::info!("No EXIF date for {:?}, using modification time", path);
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(|mtime| DateTime::<Local>::from(mtime).naive_local())
+        .unwrap_or_else(|_| {
+            log::warn!("Could not get modification time of {:?}", path);
+            NaiveDateTime::from_timestamp(0, 0)
+        })
+}
+
 fn image_dimensions(path: &Path) -> ImageResult<(u32, u32)> {
     let fin = std::fs::File::open(path)?;
     let fin = std::io::BufReader::new(fin);
@@ -111,172 +227,817 @@ fn compute_good_dimensions(
     }
     let mut ideal_w = (in_w * factor).floor() as u32;
     let mut ideal_h = (in_h * factor).floor() as u32;
-    if ideal_w % 4 != 0 {
+    if !ideal_w.is_multiple_of(4) {
         ideal_w += 4 - (ideal_w % 4);
     }
-    if ideal_h % 4 != 0 {
+    if !ideal_h.is_multiple_of(4) {
         ideal_h += 4 - (ideal_h % 4);
     }
     (ideal_w, ideal_h)
 }
 
-pub fn find_images(images: &str, im_ext: &str) -> Vec<SourceFolderInfo> {
-    let images = Path::new(&images);
-    // unwraping on pattern because a bad pattern is a programming error here
-    let mut folder_infos = Vec::new();
-    for folder in glob(&images.join("*").to_string_lossy()).unwrap() {
-        if let Ok(folder) = folder {
-            let folder_spec =
-                FolderSpec::load_or_empty(&folder.join("specs.json"));
-            let mut image_infos = Vec::new();
-            for image in
-                glob(&folder.join(format!("*.{}", im_ext)).to_string_lossy())
-                    .unwrap()
-            {
-                if let Ok(image) = image {
-                    if image.to_string_lossy().contains(" ") {
-                        log::error!(
-                            "path should not contain a space: {:?}",
-                            image,
-                        );
-                        std::process::exit(1);
-                    }
-                    let image_dims = image_dimensions(&image);
-                    let orientation = image_exif_orientation(&image);
-                    if let Ok(image_dims) = image_dims {
-                        log::info!(
-                            "Including image {:?} ({}x{})",
-                            image,
-                            image_dims.0,
-                            image_dims.1,
-                        );
-                        let basename =
-                            image.file_name().and_then(std::ffi::OsStr::to_str);
-                        let user_req = if basename
-                            .map(|name| {
-                                folder_spec
-                                    .one_portraits()
-                                    .iter()
-                                    .find(|name2| *name2 == name)
-                                    .is_some()
-                            })
-                            .unwrap_or(false)
-                        {
-                            LayoutReq::OnePortrait
-                        } else {
-                            LayoutReq::Nothing
-                        };
-                        image_infos.push(SourceImageInfo {
-                            path: image,
-                            dimensions: image_dims,
-                            orientation,
-                            user_req,
-                        });
-                    } else {
-                        log::warn!("Could not open image {:?}", image);
+/// Entries of a folder, sorted by name. Unlike glob patterns, this works
+/// with any folder name, be it valid UTF-8 or not.
+fn dir_entries(folder: &Path) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("Could not list folder {:?}: {}", folder, e);
+            return Vec::new();
+        }
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| {
+            entry
+                .map_err(|e| {
+                    log::warn!("Ignoring entry of {:?}: {}", folder, e)
+                })
+                .ok()
+        })
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn load_image(
+    image: PathBuf,
+    xmp: Option<XmpMeta>,
+    folder_spec: &FolderSpec,
+    root_spec: &RootSpec,
+) -> Option<SourceImageInfo> {
+    let image_dims = image_dimensions(&image);
+    let exif_reader = read_exif(&image);
+    let orientation = image_exif_orientation(exif_reader.as_ref(), &image);
+    let camera = image_exif_camera(exif_reader.as_ref());
+    let mut date = image_creation_date(exif_reader.as_ref(), &image);
+    let file_name = image
+        .file_name()
+        .map_or("".into(), |name| name.to_string_lossy());
+    let time_offset = folder_spec
+        .time_offset(camera.as_deref(), &file_name)
+        .or_else(|| root_spec.time_offset(camera.as_deref(), &file_name));
+    if let Some(time_offset) = time_offset {
+        log::info!(
+            "Shifting date of {:?} by {}s",
+            image,
+            time_offset.num_seconds(),
+        );
+        date += time_offset;
+    }
+    let caption = root_spec
+        .caption(&file_name)
+        .cloned()
+        .or_else(|| image_caption(exif_reader.as_ref(), xmp.as_ref(), &image));
+    if let Ok(image_dims) = image_dims {
+        log::info!(
+            "Including image {:?} ({}x{})",
+            image,
+            image_dims.0,
+            image_dims.1,
+        );
+        Some(SourceImageInfo {
+            path: image,
+            dimensions: image_dims,
+            orientation,
+            date,
+            camera,
+            caption,
+            user_req: LayoutReq::Nothing,
+        })
+    } else {
+        log::warn!("Could not open image {:?}", image);
+        None
+    }
+}
+
+/// Number of images left out of the album by a rule of the specs
+#[derive(Debug, PartialEq, Eq)]
+pub struct FilterSummary {
+    /// The rule, prefixed with the specs file defining it
+    pub rule: String,
+    pub nb_images: usize,
+}
+
+impl std::fmt::Display for FilterSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {} image(s) left out", self.rule, self.nb_images)
+    }
+}
+
+/// Images of a folder, except the ones left out by the rules of the specs
+/// or by the metadata filters, which are counted in `filtered`. `rel_folder`
+/// is the path of the folder from the images folder, empty for the images
+/// folder itself.
+fn find_folder_images(
+    folder: &Path,
+    rel_folder: &str,
+    im_ext: &str,
+    folder_spec: &FolderSpec,
+    root_spec: &RootSpec,
+    metadata_filter: &MetadataFilter,
+    filtered: &mut BTreeMap<String, usize>,
+) -> Vec<SourceImageInfo> {
+    let folder_filter = folder_spec.metadata_filter();
+    let metadata_filter = metadata_filter.not_set_in(&folder_filter);
+    let mut image_infos = Vec::new();
+    for image in dir_entries(folder) {
+        if !image.is_file() || image.extension().is_none_or(|ext| ext != im_ext)
+        {
+            continue;
+        }
+        let file_name = image
+            .file_name()
+            .map_or("".into(), |name| name.to_string_lossy());
+        let rel_path = if rel_folder.is_empty() {
+            file_name.to_string()
+        } else {
+            format!("{}/{}", rel_folder, file_name)
+        };
+        let rule = root_spec
+            .filter_rule(&file_name, &rel_path)
+            .map(|rule| format!("specs.json: {}", rule))
+            .or_else(|| {
+                folder_spec
+                    .filter_rule(&file_name)
+                    .map(|rule| format!("{}/specs.json: {}", rel_folder, rule))
+            });
+        if let Some(rule) = rule {
+            log::info!("Leaving out image {:?} ({})", image, rule);
+            *filtered.entry(rule).or_insert(0) += 1;
+            continue;
+        }
+        let xmp = XmpMeta::read(&image);
+        let (rating, tags) = xmp
+            .as_ref()
+            .map_or((0, &[][..]), |xmp| (xmp.rating.unwrap_or(0), &xmp.tags));
+        let rule = folder_filter
+            .rule(rating, tags)
+            .map(|rule| format!("{}/specs.json: {}", rel_folder, rule))
+            .or_else(|| {
+                metadata_filter
+                    .rule(rating, tags)
+                    .map(|rule| format!("command line: {}", rule))
+            });
+        if let Some(rule) = rule {
+            log::info!("Leaving out image {:?} ({})", image, rule);
+            *filtered.entry(rule).or_insert(0) += 1;
+            continue;
+        }
+        image_infos.extend(load_image(image, xmp, folder_spec, root_spec));
+    }
+    image_infos
+}
+
+/// Apply the layout requests and sort order of the folder spec to the images
+/// of a section
+fn apply_folder_spec(
+    folder_info: &mut SourceFolderInfo,
+    sort_order: SortOrder,
+    hero_fit: HeroFit,
+) {
+    let folder_spec = &folder_info.folder_spec;
+    let hero_fit = folder_spec.hero_fit().unwrap_or(hero_fit);
+    for im in &mut folder_info.image_infos {
+        let basename = im.path.file_name().and_then(std::ffi::OsStr::to_str);
+        let listed = |names: &[String]| {
+            basename.is_some_and(|name| names.iter().any(|n| n == name))
+        };
+        let page = folder_spec
+            .pages()
+            .iter()
+            .position(|page| listed(page.images()));
+        im.user_req = if let Some(page) = page {
+            LayoutReq::Pinned(page)
+        } else if listed(folder_spec.heroes()) {
+            LayoutReq::Hero(hero_fit)
+        } else if listed(folder_spec.one_portraits()) {
+            LayoutReq::OnePortrait
+        } else {
+            LayoutReq::Nothing
+        };
+        if let Some(caption) = basename.and_then(|n| folder_spec.caption(n)) {
+            im.caption = Some(caption.clone());
+        }
+    }
+    // Both sorts are stable, so images sharing a date keep their file name
+    // order.
+    let image_infos = &mut folder_info.image_infos;
+    image_infos.sort_by(|a, b| a.path.cmp(&b.path));
+    if folder_spec.sort_by().unwrap_or(sort_order) == SortOrder::Date {
+        image_infos.sort_by_key(|im| im.date);
+    }
+    group_pinned_images(image_infos, folder_spec);
+}
+
+/// Move the images of each page of the folder spec next to the first of
+/// them, in the order of the page
+fn group_pinned_images(
+    image_infos: &mut Vec<SourceImageInfo>,
+    folder_spec: &FolderSpec,
+) {
+    let name = |im: &SourceImageInfo| {
+        im.path
+            .file_name()
+            .and_then(std::ffi::OsStr::to_str)
+            .map(str::to_string)
+    };
+    let mut order = Vec::with_capacity(image_infos.len());
+    let mut placed = vec![false; image_infos.len()];
+    for i in 0..image_infos.len() {
+        if placed[i] {
+            continue;
+        }
+        match image_infos[i].user_req {
+            LayoutReq::Pinned(page) => {
+                for page_name in folder_spec.pages()[page].images() {
+                    let found = (0..image_infos.len()).find(|&j| {
+                        !placed[j]
+                            && image_infos[j].user_req
+                                == LayoutReq::Pinned(page)
+                            && name(&image_infos[j]).as_ref() == Some(page_name)
+                    });
+                    if let Some(j) = found {
+                        order.push(j);
+                        placed[j] = true;
                     }
-                } else {
-                    log::warn!("Ignoring image {:?}", image.unwrap_err());
                 }
             }
+            _ => {
+                order.push(i);
+                placed[i] = true;
+            }
+        }
+    }
+    let mut images: Vec<_> = image_infos.drain(..).map(Some).collect();
+    image_infos.extend(order.into_iter().map(|i| images[i].take().unwrap()));
+}
+
+/// Options controlling how images are found and split into sections
+#[derive(Copy, Clone, Debug)]
+pub struct FindOptions<'a> {
+    /// Extension of the image files
+    pub im_ext: &'a str,
+    pub sort_order: SortOrder,
+    /// When set, the images folder is flat and split into sections
+    pub auto_sections: Option<AutoSections>,
+    /// Language of the generated section titles
+    pub language: Language,
+    /// How hero photos fill pages of another orientation, unless their
+    /// folder spec tells otherwise
+    pub hero_fit: HeroFit,
+    /// Filters on the XMP rating and tags of the images, unless their
+    /// folder spec replaces them
+    pub metadata_filter: &'a MetadataFilter,
+    /// Report the errors of the specs as warnings, ignoring the specs that
+    /// cannot be read
+    pub lenient: bool,
+}
+
+/// Check the images named in a folder spec, `folder` being where they are
+/// looked for and `image_infos` the images kept in the section. Returns the
+/// errors, for images that do not exist, and the warnings, for requests
+/// that contradict each other or cannot be followed.
+fn check_folder_spec(
+    spec_name: &str,
+    folder_spec: &FolderSpec,
+    folder: &Path,
+    image_infos: &[SourceImageInfo],
+) -> (Vec<String>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut requests: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (key, name) in folder_spec.image_references() {
+        if !folder.join(name).is_file() {
+            errors.push(format!(
+                "{}: image {:?} of {} does not exist",
+                spec_name, name, key,
+            ));
+        } else if key != "captions" {
+            requests.entry(name).or_default().push(key);
+        }
+    }
+    for (name, keys) in requests {
+        let im = image_infos.iter().find(|im| {
+            im.path.file_name().and_then(std::ffi::OsStr::to_str) == Some(name)
+        });
+        let im = match im {
+            Some(im) => im,
+            None => {
+                warnings.push(format!(
+                    "{}: image {:?} of {} is left out of the section",
+                    spec_name, name, keys[0],
+                ));
+                continue;
+            }
+        };
+        if keys.len() > 1 {
+            warnings.push(format!(
+                "{}: image {:?} is listed in {}, only one of these is \
+                 followed",
+                spec_name,
+                name,
+                keys.join(" and "),
+            ));
+        }
+        let (width, height) = im.orientation.rotated_dims(im.dimensions);
+        if keys.contains(&"one_portraits") && width >= height {
+            warnings.push(format!(
+                "{}: image {:?} of one_portraits is not a portrait, it is \
+                 not put alone on its page",
+                spec_name, name,
+            ));
+        }
+    }
+    (errors, warnings)
+}
+
+/// Images of the album, by section, along with the number of images left
+/// out by each rule of the specs. Fails when a spec cannot be read or names
+/// images that do not exist, unless `lenient` is set.
+pub fn find_images(
+    images: &str,
+    options: FindOptions,
+) -> Result<(Vec<SourceFolderInfo>, Vec<FilterSummary>), String> {
+    let FindOptions {
+        im_ext,
+        sort_order,
+        auto_sections,
+        language,
+        hero_fit,
+        metadata_filter,
+        lenient,
+    } = options;
+    let images = Path::new(&images);
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let root_spec_path = images.join("specs.json");
+    let root_spec = RootSpec::load(&root_spec_path).unwrap_or_else(|e| {
+        errors.push(e);
+        RootSpec::default()
+    });
+    let mut folder_infos = Vec::new();
+    let mut filtered = BTreeMap::new();
+    if let Some(auto_sections) = auto_sections {
+        let image_infos = find_folder_images(
+            images,
+            "",
+            im_ext,
+            &FolderSpec::empty(),
+            &root_spec,
+            metadata_filter,
+            &mut filtered,
+        );
+        folder_infos = sections::split_sections(
+            image_infos,
+            auto_sections,
+            &root_spec,
+            language,
+        );
+        for (key, section_spec) in root_spec.section_specs() {
+            let spec_name =
+                format!("{}: section {}", root_spec_path.display(), key);
+            let section = folder_infos.iter().find(|section| {
+                section.image_infos.first().is_some_and(|im| {
+                    im.date.format("%Y-%m-%d").to_string() == key
+                })
+            });
+            for key in section_spec.image_selection_keys() {
+                errors.push(format!(
+                    "{}: {} cannot be set for a generated section, as it \
+                     applies before the images are split into sections",
+                    spec_name, key,
+                ));
+            }
+            if section.is_none() {
+                warnings
+                    .push(format!("{}: no section starts that day", spec_name));
+            }
+            let image_infos = section.map_or(&[][..], |s| &s.image_infos);
+            let (section_errors, section_warnings) = check_folder_spec(
+                &spec_name,
+                section_spec,
+                images,
+                image_infos,
+            );
+            errors.extend(section_errors);
+            warnings.extend(section_warnings);
+        }
+    } else {
+        for folder in dir_entries(images) {
+            if !folder.is_dir() {
+                continue;
+            }
+            let rel_folder = folder
+                .file_name()
+                .map_or("".into(), |name| name.to_string_lossy());
+            let spec_path = folder.join("specs.json");
+            let spec_name = spec_path.display().to_string();
+            let folder_spec =
+                FolderSpec::load(&spec_path).unwrap_or_else(|e| {
+                    errors.push(e);
+                    FolderSpec::empty()
+                });
+            let image_infos = find_folder_images(
+                &folder,
+                &rel_folder,
+                im_ext,
+                &folder_spec,
+                &root_spec,
+                metadata_filter,
+                &mut filtered,
+            );
+            let (folder_errors, folder_warnings) = check_folder_spec(
+                &spec_name,
+                &folder_spec,
+                &folder,
+                &image_infos,
+            );
+            errors.extend(folder_errors);
+            warnings.extend(folder_warnings);
             folder_infos.push(SourceFolderInfo {
                 image_infos,
                 folder_spec,
             });
-        } else {
-            log::warn!("Ignoring folder {:?}", folder.unwrap_err());
         }
+        if root_spec.section_specs().next().is_some() {
+            warnings.push(format!(
+                "{}: sections are only used with --auto-sections",
+                root_spec_path.display(),
+            ));
+        }
+    }
+    let folders = match auto_sections {
+        Some(_) => vec![images.to_path_buf()],
+        None => dir_entries(images),
+    };
+    for name in root_spec.captioned_images() {
+        if !folders.iter().any(|folder| folder.join(name).is_file()) {
+            errors.push(format!(
+                "{}: image {:?} of captions does not exist",
+                root_spec_path.display(),
+                name,
+            ));
+        }
+    }
+    for warning in &warnings {
+        log::warn!("{}", warning);
+    }
+    if lenient {
+        for error in &errors {
+            log::warn!("{}", error);
+        }
+    } else if !errors.is_empty() {
+        for error in &errors {
+            log::error!("{}", error);
+        }
+        return Err(format!(
+            "{} error(s) in the specs, use --lenient to ignore them",
+            errors.len(),
+        ));
+    }
+    for folder_info in &mut folder_infos {
+        apply_folder_spec(folder_info, sort_order, hero_fit);
+    }
+    if sort_order == SortOrder::Date {
+        folder_infos.sort_by_key(|folder| {
+            folder.image_infos.iter().map(|im| im.date).min()
+        });
+    }
+    let filtered = filtered
+        .into_iter()
+        .map(|(rule, nb_images)| FilterSummary { rule, nb_images })
+        .collect();
+    Ok((folder_infos, filtered))
+}
+
+/// Number of photos taken by a camera, and the dates of the first and last
+/// ones once clock offsets are applied.
+#[derive(Debug)]
+pub struct CameraSummary {
+    pub camera: Option<String>,
+    pub nb_photos: usize,
+    pub first: NaiveDateTime,
+    pub last: NaiveDateTime,
+}
+
+impl std::fmt::Display for CameraSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} photo(s), from {} to {}",
+            self.camera.as_deref().unwrap_or("unknown camera"),
+            self.nb_photos,
+            self.first.format("%Y-%m-%d %H:%M"),
+            self.last.format("%Y-%m-%d %H:%M"),
+        )
+    }
+}
+
+/// List the cameras found in the EXIF data of all the images
+pub fn camera_summaries(
+    folder_infos: &[SourceFolderInfo],
+) -> Vec<CameraSummary> {
+    let mut summaries: BTreeMap<Option<&str>, CameraSummary> = BTreeMap::new();
+    for im in folder_infos.iter().flat_map(|f| &f.image_infos) {
+        let summary =
+            summaries.entry(im.camera.as_deref()).or_insert_with(|| {
+                CameraSummary {
+                    camera: im.camera.clone(),
+                    nb_photos: 0,
+                    first: im.date,
+                    last: im.date,
+                }
+            });
+        summary.nb_photos += 1;
+        summary.first = summary.first.min(im.date);
+        summary.last = summary.last.max(im.date);
+    }
+    summaries.into_values().collect()
+}
+
+/// 32 bits FNV-1a hash, stable across platforms and compiler versions
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &b| {
+        (hash ^ u32::from(b)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// File name of the resized copy of an image. Only ASCII letters, digits,
+/// '-' and '_' are kept so that the name can go through LaTeX. When the name
+/// has to be changed, a hash of the original one is appended to avoid
+/// collisions.
+fn resized_file_name(source: &Path) -> String {
+    let stem = source
+        .file_stem()
+        .map_or("".into(), |stem| stem.to_string_lossy());
+    let mut name = String::with_capacity(stem.len());
+    for c in stem.chars() {
+        if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            name.push(c);
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    if name.is_empty() || name != stem {
+        let original = source.file_name().unwrap_or_default();
+        name.push_str(&format!("_{:08x}", fnv1a(&file_name_bytes(original))));
+    }
+    // resized images are always written as JPEG
+    format!("{}.jpg", name)
+}
+
+/// Bytes of a file name, to hash it. On Unix, the raw bytes keep non UTF-8
+/// names distinct.
+#[cfg(unix)]
+fn file_name_bytes(name: &std::ffi::OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    name.as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn file_name_bytes(name: &std::ffi::OsStr) -> Vec<u8> {
+    name.to_string_lossy().as_bytes().to_vec()
+}
+
+/// Settings used to write the resized copies, any change to them invalidates
+/// the resize cache
+const JPEG_QUALITY: u8 = 90;
+const RESIZE_FILTER: image::FilterType = image::FilterType::Gaussian;
+
+fn resize_image(
+    source: &SourceImageInfo,
+    target: &ImageInfo,
+    color: ColorMode,
+) -> ImageResult<()> {
+    let im_path = &source.path;
+    let resized_path = &target.path;
+    let im = image::open(im_path).map_err(|e| {
+        log::error!("error opening image {:?}: {}", im_path, e);
+        e
+    })?;
+    log::info!("resizing {:?}", im_path);
+    let (w, h) = target.resize_dims;
+    let im = im.resize(w, h, RESIZE_FILTER);
+    let im = apply_orientation(im, source.orientation);
+    let im = match color {
+        ColorMode::Rgb => im,
+        ColorMode::Grayscale => im.grayscale(),
+    };
+    // should not have a bad path at this point: SourceImageInfo
+    // is trusted
+    if let Some(folder_path) = resized_path.parent() {
+        std::fs::create_dir_all(folder_path)?;
+    }
+    let mut out_file =
+        std::io::BufWriter::new(std::fs::File::create(resized_path)?);
+    im.write_to(&mut out_file, ImageOutputFormat::JPEG(JPEG_QUALITY))?;
+    Ok(())
+}
+
+/// Folder of the copies of the resized images made for a single page, kept
+/// apart so that they cannot overwrite a resized image of the same name
+const DERIVED_FOLDER: &str = "derived";
+
+/// Path of a copy of the resized image `path` made for a single page, such
+/// as `derived/IMG_1_hero.jpg` for `IMG_1.jpg`
+fn derived_path(path: &Path, suffix: &str) -> std::io::Result<PathBuf> {
+    let folder = path.with_file_name(DERIVED_FOLDER);
+    std::fs::create_dir_all(&folder)?;
+    let stem = path.file_stem().map_or("".into(), |s| s.to_string_lossy());
+    Ok(folder.join(format!("{}_{}.jpg", stem, suffix)))
+}
+
+/// Write the left and right halves of a resized image, for the two pages of
+/// a spread, and return their paths
+pub(crate) fn split_in_halves(path: &Path) -> ImageResult<(PathBuf, PathBuf)> {
+    use image::GenericImageView;
+    let mut im = image::open(path)?;
+    let (width, height) = im.dimensions();
+    let left_path = derived_path(path, "left")?;
+    let right_path = derived_path(path, "right")?;
+    let halves = [
+        (&left_path, im.crop(0, 0, width / 2, height)),
+        (
+            &right_path,
+            im.crop(width / 2, 0, width - width / 2, height),
+        ),
+    ];
+    for (half_path, half) in &halves {
+        let mut out_file =
+            std::io::BufWriter::new(std::fs::File::create(half_path)?);
+        half.write_to(&mut out_file, ImageOutputFormat::JPEG(JPEG_QUALITY))?;
+    }
+    Ok((left_path, right_path))
+}
+
+/// Part of an image of `dims` shown on a hero page of `page_dims`, as its
+/// left, top, width and height in pixels. With `HeroFit::Crop`, the image is
+/// scaled to the width of the page and only what overflows its height is
+/// cut, evenly at the top and the bottom. A rotated image is kept whole.
+pub(crate) fn hero_crop(
+    dims: (u32, u32),
+    page_dims: (f32, f32),
+    fit: HeroFit,
+) -> (u32, u32, u32, u32) {
+    let (width, height) = dims;
+    let page_ratio = page_dims.0 / page_dims.1;
+    if fit == HeroFit::Rotate || width as f32 / height as f32 >= page_ratio {
+        return (0, 0, width, height);
+    }
+    let crop_height = ((width as f32 / page_ratio).round() as u32).min(height);
+    (0, (height - crop_height) / 2, width, crop_height)
+}
+
+/// Write a copy of a resized image for a hero page of `page_dims`, turned a
+/// quarter counterclockwise first when `rotate` is set and cropped as
+/// `hero_crop` tells. Return its path and dimensions.
+pub(crate) fn crop_to_page(
+    path: &Path,
+    page_dims: (f32, f32),
+    fit: HeroFit,
+    rotate: bool,
+) -> ImageResult<(PathBuf, (u32, u32))> {
+    use image::GenericImageView;
+    let mut im = image::open(path)?;
+    if rotate {
+        im = im.rotate270();
+    }
+    let (left, top, width, height) = hero_crop(im.dimensions(), page_dims, fit);
+    let im = im.crop(left, top, width, height);
+    let hero_path = derived_path(path, "hero")?;
+    let mut out_file =
+        std::io::BufWriter::new(std::fs::File::create(&hero_path)?);
+    im.write_to(&mut out_file, ImageOutputFormat::JPEG(JPEG_QUALITY))?;
+    Ok((hero_path, (width, height)))
+}
+
+/// Turn an image upright
+fn apply_orientation(
+    im: image::DynamicImage,
+    orientation: Orientation,
+) -> image::DynamicImage {
+    match orientation {
+        Orientation::Rotate90 => im.rotate90(),
+        Orientation::Rotate180 => im.rotate180(),
+        Orientation::Rotate270 => im.rotate270(),
+        Orientation::FlipHorizontal => im.fliph(),
+        Orientation::FlipVertical => im.flipv(),
+        Orientation::Transpose => im.rotate90().fliph(),
+        Orientation::Transverse => im.rotate90().flipv(),
+        Orientation::Keep | Orientation::Unknown => im,
     }
-    folder_infos
 }
 
+/// Copy the images, resized for printing, in `images_path`. Only the images
+/// missing from the resize cache, or whose source, dimensions or orientation
+/// have changed, are resized. Stale images left by previous runs are
+/// removed.
 pub fn resize_images(
     folder_infos: Vec<SourceFolderInfo>,
     dpm: f32,
     page_dims: (f32, f32),
+    color: ColorMode,
     images_path: &Path,
 ) -> Result<Vec<FolderInfo>, Box<dyn Error>> {
     let mut res = Vec::with_capacity(folder_infos.len());
+    let mut sources = Vec::new();
     for (ind, source_folder) in folder_infos.into_iter().enumerate() {
         let mut image_infos =
             Vec::with_capacity(source_folder.image_infos.len());
         let folder_path = images_path.join(format!("section_{:02}", ind));
-        std::fs::create_dir_all(&folder_path)?;
         for im_info in &source_folder.image_infos {
-            let ideal_dims =
-                compute_good_dimensions(im_info.dimensions, page_dims, dpm);
-            let im_path = &im_info.path;
-            let resized_path = folder_path.join(im_path.file_name().unwrap());
-            let rotated_dims = match im_info.orientation {
-                Orientation::Rotate90 | Orientation::Rotate270 => {
-                    (ideal_dims.1, ideal_dims.0)
-                }
-                _ => ideal_dims,
+            // the page dimensions are compared to the image as stored,
+            // before it is turned upright
+            let stored_page_dims = if im_info.orientation.swaps_dims() {
+                (page_dims.1, page_dims.0)
+            } else {
+                page_dims
             };
+            let ideal_dims = compute_good_dimensions(
+                im_info.dimensions,
+                stored_page_dims,
+                dpm,
+            );
+            let resized_path =
+                folder_path.join(resized_file_name(&im_info.path));
+            log::info!(
+                "{:?} will be copied to {:?}",
+                im_info.path,
+                resized_path
+            );
+            let rotated_dims = im_info.orientation.rotated_dims(ideal_dims);
             image_infos.push(ImageInfo {
                 resize_dims: ideal_dims,
                 path: resized_path,
+                source_path: im_info.path.clone(),
                 rotated_dims,
+                date: im_info.date,
+                caption: im_info.caption.clone(),
                 user_req: im_info.user_req,
             });
         }
-        source_folder
-            .image_infos
-            .par_iter()
-            .zip(&image_infos)
-            .map(|(source, target)| {
-                let im_path = &source.path;
-                let resized_path = &target.path;
-
-                // early check if resizing is necessary
-                let in_mtime =
-                    std::fs::metadata(im_path).and_then(|x| x.modified());
-                let out_mtime =
-                    std::fs::metadata(resized_path).and_then(|x| x.modified());
-                if let (Ok(in_mtime), Ok(out_mtime)) = (in_mtime, out_mtime) {
-                    if in_mtime <= out_mtime {
-                        log::info!(
-                            "no need to resize {:?}, up to date",
-                            im_path
-                        );
-                        return Ok(());
-                    }
-                }
-
-                let im = image::open(im_path).map_err(|e| {
-                    log::error!("error opening image {:?}: {}", im_path, e);
-                    e
-                })?;
-                log::info!("resizing {:?}", im_path);
-                let (w, h) = target.resize_dims;
-                let im = im.resize(w, h, image::FilterType::Gaussian);
-                let im = match source.orientation {
-                    Orientation::Rotate90 => im.rotate90(),
-                    Orientation::Rotate180 => im.rotate180(),
-                    Orientation::Rotate270 => im.rotate270(),
-                    Orientation::Flipped => {
-                        log::info!(
-                            "Refusing to modify flipped image {:?}",
-                            im_path,
-                        );
-                        im
-                    }
-                    _ => im,
-                };
-                // should not have a bad path at this point: SourceImageInfo
-                // is trusted
-                let mut out_file = std::io::BufWriter::new(
-                    std::fs::File::create(&resized_path)?,
-                );
-                im.write_to(&mut out_file, ImageOutputFormat::JPEG(90))?;
-                Ok(())
-            })
-            .collect::<ImageResult<()>>()?;
+        sources.extend(source_folder.image_infos);
         res.push(FolderInfo {
             image_infos,
             folder_spec: source_folder.folder_spec,
         });
     }
+    let targets: Vec<_> = res.iter().flat_map(|f| &f.image_infos).collect();
+    let rel_paths = targets
+        .iter()
+        .map(|target| tex_path(&target.path, images_path))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let encoder =
+        format!("jpeg {} {:?} {:?}", JPEG_QUALITY, RESIZE_FILTER, color);
+    let entries = sources
+        .par_iter()
+        .zip(&targets)
+        .map(|(source, target)| {
+            CacheEntry::new(
+                &source.path,
+                target.resize_dims,
+                &format!("{:?}", source.orientation),
+                &encoder,
+            )
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    // Outdated entries are dropped from the cache before any image is
+    // written, so that an interrupted run cannot leave wrong entries.
+    std::fs::create_dir_all(images_path)?;
+    let mut cache = ResizeCache::load(images_path);
+    let fresh: BTreeMap<_, _> = rel_paths
+        .iter()
+        .zip(&entries)
+        .map(|(rel_path, entry)| {
+            let fresh = cache.is_fresh(images_path, rel_path, entry);
+            (rel_path.as_str(), fresh)
+        })
+        .collect();
+    cache.retain(|rel_path| fresh.get(rel_path) == Some(&true));
+    cache.save(images_path)?;
+    resize_cache::remove_stale_images(images_path, &|rel_path| {
+        fresh.contains_key(rel_path)
+    })?;
+
+    sources
+        .par_iter()
+        .zip(&targets)
+        .zip(&rel_paths)
+        .map(|((source, target), rel_path)| {
+            if fresh[rel_path.as_str()] {
+                log::info!("no need to resize {:?}, up to date", source.path);
+                Ok(())
+            } else {
+                resize_image(source, target, color)
+            }
+        })
+        .collect::<ImageResult<()>>()?;
+    for (rel_path, entry) in rel_paths.iter().zip(entries) {
+        cache.insert(rel_path.clone(), entry);
+    }
+    cache.save(images_path)?;
     Ok(res)
 }
 
@@ -290,4 +1051,181 @@ mod test {
         assert!(ideal_w < 5184);
         assert!(ideal_h < 3456);
     }
+
+    #[test]
+    fn hero_crop() {
+        use super::{hero_crop, HeroFit};
+
+        let a4_portrait = (210., 297.);
+        // a landscape fills the width of the page and is kept whole
+        assert_eq!(
+            hero_crop((600, 400), a4_portrait, HeroFit::Crop),
+            (0, 0, 600, 400)
+        );
+        // a tall photo loses what overflows the height of the page
+        assert_eq!(
+            hero_crop((400, 1000), a4_portrait, HeroFit::Crop),
+            (0, 217, 400, 566)
+        );
+        assert_eq!(
+            hero_crop((400, 1000), a4_portrait, HeroFit::Rotate),
+            (0, 0, 400, 1000)
+        );
+    }
+
+    #[test]
+    fn resized_file_name() {
+        use std::path::Path;
+
+        use super::resized_file_name;
+        assert_eq!(
+            resized_file_name(Path::new("a/IMG_0001.jpg")),
+            "IMG_0001.jpg"
+        );
+        assert_eq!(resized_file_name(Path::new("a/photo.png")), "photo.jpg");
+        let spaced = resized_file_name(Path::new("a/IMG 0001.jpg"));
+        let accented = resized_file_name(Path::new("a/Été à Brest.jpg"));
+        // the hash only depends on the bytes of the name, so that the resize
+        // cache stays valid
+        assert_eq!(spaced, "IMG_0001_83afe0d2.jpg");
+        assert!(accented.starts_with("_t_Brest_"));
+        assert_ne!(spaced, resized_file_name(Path::new("a/IMG-0001.jpg")));
+        assert!(accented
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.'));
+    }
+
+    #[test]
+    fn apply_orientation() {
+        use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+        use super::apply_orientation;
+        use crate::Orientation;
+
+        // 3x2 image with a single white pixel at the top left corner, the
+        // orientation is given by where it ends up.
+        let mut im = RgbImage::new(3, 2);
+        im.put_pixel(0, 0, Rgb([255, 255, 255]));
+        let white_pixel = |orientation: u32| {
+            let orientation = Orientation::from_exif(orientation);
+            let im = DynamicImage::ImageRgb8(im.clone());
+            let im = apply_orientation(im, orientation);
+            assert_eq!(im.dimensions(), orientation.rotated_dims((3, 2)));
+            im.pixels()
+                .find(|(_, _, p)| p[0] == 255)
+                .map(|(x, y, _)| (x, y))
+                .unwrap()
+        };
+        assert_eq!(white_pixel(1), (0, 0));
+        assert_eq!(white_pixel(2), (2, 0));
+        assert_eq!(white_pixel(3), (2, 1));
+        assert_eq!(white_pixel(4), (0, 1));
+        assert_eq!(white_pixel(5), (0, 0));
+        assert_eq!(white_pixel(6), (1, 0));
+        assert_eq!(white_pixel(7), (1, 2));
+        assert_eq!(white_pixel(8), (0, 2));
+    }
+
+    #[test]
+    fn pinned_images_are_grouped() {
+        use std::path::PathBuf;
+
+        use super::{apply_folder_spec, HeroFit, SortOrder};
+        use crate::{
+            LayoutReq, Orientation, SourceFolderInfo, SourceImageInfo,
+        };
+
+        let image = |name: &str| SourceImageInfo {
+            path: PathBuf::from(name),
+            dimensions: (400, 300),
+            orientation: Orientation::Keep,
+            date: "2016-08-09T10:00:00".parse().unwrap(),
+            camera: None,
+            caption: None,
+            user_req: LayoutReq::Nothing,
+        };
+        let mut folder_info = SourceFolderInfo {
+            folder_spec: serde_json::from_str(
+                r#"{"pages": [["e.jpg", "b.jpg"], ["c.jpg"]]}"#,
+            )
+            .unwrap(),
+            image_infos: ["a.jpg", "b.jpg", "c.jpg", "d.jpg", "e.jpg"]
+                .iter()
+                .map(|name| image(name))
+                .collect(),
+        };
+        apply_folder_spec(&mut folder_info, SortOrder::Name, HeroFit::Crop);
+        let images: Vec<_> = folder_info
+            .image_infos
+            .iter()
+            .map(|im| (im.path.to_str().unwrap(), im.user_req))
+            .collect();
+        assert_eq!(
+            images,
+            [
+                ("a.jpg", LayoutReq::Nothing),
+                ("e.jpg", LayoutReq::Pinned(0)),
+                ("b.jpg", LayoutReq::Pinned(0)),
+                ("c.jpg", LayoutReq::Pinned(1)),
+                ("d.jpg", LayoutReq::Nothing),
+            ],
+        );
+    }
+
+    #[test]
+    fn folder_spec_checks() {
+        use super::check_folder_spec;
+        use crate::{LayoutReq, Orientation, SourceImageInfo};
+
+        let folder = std::env::temp_dir().join("phototex_spec_checks");
+        std::fs::create_dir_all(&folder).unwrap();
+        for name in &["a.jpg", "b.jpg", "c.jpg"] {
+            std::fs::write(folder.join(name), b"").unwrap();
+        }
+        let image = |name: &str, dimensions| SourceImageInfo {
+            path: folder.join(name),
+            dimensions,
+            orientation: Orientation::Keep,
+            date: "2016-08-09T10:00:00".parse().unwrap(),
+            camera: None,
+            caption: None,
+            user_req: LayoutReq::Nothing,
+        };
+        let folder_spec = serde_json::from_str(
+            r#"{"one_portraits": ["a.jpg", "b.jpg"], "heroes": ["b.jpg"],
+                "pages": [["c.jpg"]], "captions": {"d.jpg": "missing"}}"#,
+        )
+        .unwrap();
+        // c.jpg was left out by a filter
+        let image_infos =
+            [image("a.jpg", (600, 400)), image("b.jpg", (400, 600))];
+        let (errors, warnings) = check_folder_spec(
+            "specs.json",
+            &folder_spec,
+            &folder,
+            &image_infos,
+        );
+        assert_eq!(
+            errors,
+            [r#"specs.json: image "d.jpg" of captions does not exist"#],
+        );
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].contains(r#""a.jpg" of one_portraits is not a"#));
+        assert!(warnings[1].contains("in one_portraits and heroes"));
+        assert!(warnings[2].contains(r#""c.jpg" of pages is left out"#));
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn image_creation_date_falls_back_to_mtime() {
+        let path = std::env::temp_dir().join("phototex_no_exif.jpg");
+        std::fs::write(&path, b"not really a jpeg").unwrap();
+        let reader = super::read_exif(&path);
+        assert!(reader.is_none());
+        let date = super::image_creation_date(reader.as_ref(), &path);
+        let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+        let mtime = chrono::DateTime::<chrono::Local>::from(mtime);
+        assert_eq!(date, mtime.naive_local());
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,169 @@
+//! Conversion of user supplied text to LaTeX
+use serde::Deserialize;
+
+/// Text written into the LaTeX templates. In `specs.json`, a plain string is
+/// escaped, while `{"latex": "..."}` is written as is, allowing formatting.
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum TexText {
+    Plain(String),
+    Latex { latex: String },
+}
+
+impl TexText {
+    pub fn plain<S: Into<String>>(text: S) -> Self {
+        TexText::Plain(text.into())
+    }
+
+    pub fn latex<S: Into<String>>(latex: S) -> Self {
+        TexText::Latex {
+            latex: latex.into(),
+        }
+    }
+
+    /// The text as it should be written in a LaTeX file
+    pub fn to_latex(&self) -> String {
+        match self {
+            TexText::Plain(text) => escape(text),
+            TexText::Latex { latex } => latex.clone(),
+        }
+    }
+
+    /// The text without LaTeX markup, for file names: commands and braces
+    /// are dropped, while escaped characters such as `\&` are kept
+    pub fn plain_text(&self) -> String {
+        let latex = match self {
+            TexText::Plain(text) => return text.clone(),
+            TexText::Latex { latex } => latex,
+        };
+        let mut res = String::with_capacity(latex.len());
+        let mut chars = latex.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.peek() {
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        while chars
+                            .peek()
+                            .is_some_and(char::is_ascii_alphabetic)
+                        {
+                            chars.next();
+                        }
+                    }
+                    Some('\\') => {
+                        chars.next();
+                        res.push(' ');
+                    }
+                    Some(_) => res.extend(chars.next()),
+                    None => (),
+                },
+                '{' | '}' | '$' => (),
+                '~' => res.push(' '),
+                _ => res.push(c),
+            }
+        }
+        res.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// LaTeX replacement for a character, when it cannot be written as is with
+/// the utf8 inputenc and T1 font encoding
+fn replacement(c: char) -> Option<&'static str> {
+    let res = match c {
+        '&' => "\\&",
+        '%' => "\\%",
+        '$' => "\\$",
+        '#' => "\\#",
+        '_' => "\\_",
+        '{' => "\\{",
+        '}' => "\\}",
+        '~' => "\\textasciitilde{}",
+        '^' => "\\textasciicircum{}",
+        '\\' => "\\textbackslash{}",
+        '<' => "\\textless{}",
+        '>' => "\\textgreater{}",
+        '|' => "\\textbar{}",
+        '\u{a0}' => "~",
+        '\u{202f}' => "\\,",
+        '\u{2026}' => "\\ldots{}",
+        '\u{20ac}' => "\\texteuro{}",
+        '\u{b0}' => "\\textdegree{}",
+        '\u{d7}' => "\\texttimes{}",
+        '\u{2022}' => "\\textbullet{}",
+        '\u{2212}' => "\\textminus{}",
+        '\u{2192}' => "\\textrightarrow{}",
+        '\u{2190}' => "\\textleftarrow{}",
+        _ => return None,
+    };
+    Some(res)
+}
+
+/// Characters the LaTeX preamble can typeset without any replacement
+fn is_supported(c: char) -> bool {
+    match c {
+        ' '..='~' => true,
+        // Latin-1 supplement and Latin Extended-A, covered by T1
+        '\u{a1}'..='\u{17f}' => true,
+        // dashes, quotes and guillemets
+        '\u{2013}' | '\u{2014}' | '\u{2018}'..='\u{201e}' => true,
+        '\u{2039}' | '\u{203a}' => true,
+        _ => false,
+    }
+}
+
+/// Escape text so that it is typeset as is by LaTeX. Characters the
+/// document cannot typeset are dropped with a warning rather than breaking
+/// the compilation.
+pub fn escape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        if let Some(replacement) = replacement(c) {
+            res.push_str(replacement);
+        } else if c.is_whitespace() {
+            res.push(' ');
+        } else if is_supported(c) {
+            res.push(c);
+        } else {
+            log::warn!(
+                "dropping character {:?} (U+{:04X}) unsupported by LaTeX \
+                 in {:?}",
+                c,
+                c as u32,
+                text,
+            );
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape, TexText};
+
+    #[test]
+    fn escape_special_chars() {
+        assert_eq!(
+            escape("Fish & Chips – 100% Irish"),
+            "Fish \\& Chips – 100\\% Irish",
+        );
+        assert_eq!(
+            escape("$5 #1 a_b {x} ~ ^ \\"),
+            "\\$5 \\#1 a\\_b \\{x\\} \\textasciitilde{} \\textasciicircum{} \
+             \\textbackslash{}",
+        );
+        assert_eq!(escape("Café… 20\u{a0}€"), "Café\\ldots{} 20~\\texteuro{}");
+        assert_eq!(escape("sunset \u{1f305}\nover"), "sunset  over");
+    }
+
+    #[test]
+    fn tex_text_from_json() {
+        let texts: Vec<TexText> =
+            serde_json::from_str(r#"["50%", {"latex": "\\textbf{50}\\%"}]"#)
+                .unwrap();
+        assert_eq!(texts[0].to_latex(), "50\\%");
+        assert_eq!(texts[1].to_latex(), "\\textbf{50}\\%");
+        assert_eq!(texts[0].plain_text(), "50%");
+        assert_eq!(texts[1].plain_text(), "50%");
+        let title = TexText::latex("Chambord \\textit{(Loir-et-Cher)}\\\\2016");
+        assert_eq!(title.plain_text(), "Chambord (Loir-et-Cher) 2016");
+    }
+}
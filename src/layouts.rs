@@ -0,0 +1,241 @@
+//! Descriptions of the layouts of the pages of photos, made of slots each
+//! holding one photo
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::ImageInfo;
+
+/// Height of the area of the pages holding the photos, below the headings,
+/// relative to the text height
+pub const PHOTO_AREA_HEIGHT: f32 = 0.93;
+/// Height kept for the caption in a slot, relative to the photo area
+pub const CAPTION_HEIGHT: f32 = 0.05;
+/// Maximum number of slots of a layout
+pub const MAX_SLOTS: usize = 4;
+/// Aspect ratio from which a photo is a panorama rather than a landscape
+pub const PANORAMA_RATIO: f32 = 2.5;
+
+/// Orientation of the photos expected in a slot
+#[derive(Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SlotOrientation {
+    #[default]
+    Any,
+    Landscape,
+    Portrait,
+    /// Photos at least `PANORAMA_RATIO` times as wide as high
+    Panorama,
+}
+
+impl SlotOrientation {
+    /// Orientation of a photo
+    pub fn of(im: &ImageInfo) -> SlotOrientation {
+        let (width, height) = im.rotated_dims;
+        if width as f32 >= PANORAMA_RATIO * height as f32 {
+            SlotOrientation::Panorama
+        } else if width >= height {
+            SlotOrientation::Landscape
+        } else {
+            SlotOrientation::Portrait
+        }
+    }
+}
+
+#[derive(Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptionPosition {
+    #[default]
+    Below,
+    Above,
+    /// The captions of the photos in this slot are not shown
+    None,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct Slot {
+    pub name: String,
+    /// Left, top, width and height of the slot, relative to the width and
+    /// height of the photo area
+    pub rect: (f32, f32, f32, f32),
+    #[serde(default)]
+    pub orientation: SlotOrientation,
+    #[serde(default)]
+    pub caption: CaptionPosition,
+}
+
+impl Slot {
+    /// Width and height of the box of the photo, relative to the width and
+    /// height of the photo area
+    pub fn photo_rect(&self) -> (f32, f32) {
+        match self.caption {
+            CaptionPosition::None => (self.rect.2, self.rect.3),
+            _ => (self.rect.2, self.rect.3 - CAPTION_HEIGHT),
+        }
+    }
+
+    /// Whether the orientation of the photo is the expected one
+    pub fn fits(&self, im: &ImageInfo) -> bool {
+        self.orientation == SlotOrientation::Any
+            || self.orientation == SlotOrientation::of(im)
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct Layout {
+    pub name: String,
+    /// Slots in the order the photos are read
+    pub slots: Vec<Slot>,
+    /// Whether the layout covers two facing pages, its single slot being
+    /// relative to both photo areas side by side and centered on the fold.
+    /// Spreads only get photos of the orientation of their slot.
+    #[serde(default)]
+    pub spread: bool,
+}
+
+impl Layout {
+    /// Number of pages covered by the layout
+    pub fn nb_pages(&self) -> usize {
+        if self.spread {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.slots.is_empty() || self.slots.len() > MAX_SLOTS {
+            return Err(format!(
+                "layout {} must have from 1 to {} slots",
+                self.name, MAX_SLOTS,
+            ));
+        }
+        for slot in &self.slots {
+            let (left, top, width, height) = slot.rect;
+            let inside = left >= 0.
+                && top >= 0.
+                && left + width <= 1.001
+                && top + height <= 1.001;
+            if !inside || width <= 0. || slot.photo_rect().1 <= 0. {
+                return Err(format!(
+                    "slot {} of layout {} is not inside the photo area",
+                    slot.name, self.name,
+                ));
+            }
+            if self.spread && (left + width / 2. - 0.5).abs() > 0.001 {
+                return Err(format!(
+                    "slot {} of spread {} is not centered on the fold",
+                    slot.name, self.name,
+                ));
+            }
+        }
+        if self.spread && self.slots.len() > 1 {
+            return Err(format!("spread {} must have one slot", self.name));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn parse_layouts(layouts: &str) -> Result<Vec<Layout>, String> {
+    let layouts: Vec<Layout> =
+        serde_json::from_str(layouts).map_err(|e| e.to_string())?;
+    for layout in &layouts {
+        layout.check()?;
+    }
+    Ok(layouts)
+}
+
+/// Layouts shipped with phototex
+pub fn builtin_layouts() -> Vec<Layout> {
+    parse_layouts(include_str!("../data/layouts.json"))
+        .expect("invalid builtin layouts")
+}
+
+/// The builtin layouts, along with the ones of a user layouts file, which
+/// replace the builtin layouts of the same name
+pub fn load_layouts(
+    user_layouts: Option<&Path>,
+) -> Result<Vec<Layout>, String> {
+    let mut layouts = builtin_layouts();
+    if let Some(path) = user_layouts {
+        let user_layouts = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {:?}: {}", path, e))?;
+        let user_layouts = parse_layouts(&user_layouts)
+            .map_err(|e| format!("invalid layouts file {:?}: {}", path, e))?;
+        for layout in user_layouts {
+            layouts.retain(|l| l.name != layout.name);
+            layouts.push(layout);
+        }
+    }
+    // any photo can go alone on a page, whatever the other layouts
+    if !layouts.iter().any(|l| l.slots.len() == 1 && !l.spread) {
+        return Err(
+            "the layouts must include one of a single slot, which is not a \
+             spread"
+                .to_string(),
+        );
+    }
+    Ok(layouts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        builtin_layouts, load_layouts, parse_layouts, CaptionPosition,
+    };
+
+    #[test]
+    fn builtin_layouts_are_valid() {
+        let layouts = builtin_layouts();
+        assert_eq!(layouts.len(), 11);
+        assert_eq!(layouts.iter().filter(|l| l.spread).count(), 1);
+        assert_eq!(layouts[0].slots[0].photo_rect(), (0.9, 0.95));
+    }
+
+    #[test]
+    fn user_layouts() {
+        let layouts = parse_layouts(
+            r#"[{"name": "big_and_small", "slots": [
+                {"name": "big", "rect": [0, 0, 1, 0.7],
+                 "orientation": "landscape"},
+                {"name": "small", "rect": [0.3, 0.7, 0.4, 0.3],
+                 "caption": "none"}
+            ]}]"#,
+        )
+        .unwrap();
+        assert_eq!(layouts[0].slots[1].caption, CaptionPosition::None);
+        assert_eq!(layouts[0].slots[1].photo_rect(), (0.4, 0.3));
+        let outside = r#"[{"name": "outside", "slots": [
+            {"name": "photo", "rect": [0.5, 0, 0.6, 1]}
+        ]}]"#;
+        assert!(parse_layouts(outside).is_err());
+        assert!(parse_layouts(r#"[{"name": "empty", "slots": []}]"#).is_err());
+        let off_center = r#"[{"name": "off_center", "spread": true, "slots": [
+            {"name": "photo", "rect": [0, 0, 0.8, 1]}
+        ]}]"#;
+        assert!(parse_layouts(off_center).is_err());
+    }
+
+    #[test]
+    fn single_photo_layout_is_kept() {
+        let path = std::env::temp_dir().join("phototex_layouts.json");
+        let load = |layouts: &str| {
+            std::fs::write(&path, layouts).unwrap();
+            load_layouts(Some(&path))
+        };
+        let two_rows = |name| {
+            format!(
+                r#"{{"name": "{}", "slots": [
+                    {{"name": "top", "rect": [0, 0, 1, 0.5]}},
+                    {{"name": "bottom", "rect": [0, 0.5, 1, 0.5]}}
+                ]}}"#,
+                name,
+            )
+        };
+        assert!(load(&format!("[{}]", two_rows("one"))).is_ok());
+        let replaced =
+            format!("[{}, {}]", two_rows("one"), two_rows("panorama"));
+        assert!(load(&replaced).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
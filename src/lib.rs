@@ -1,14 +1,29 @@
 use std::path::{Path, PathBuf};
 
+use chrono::NaiveDateTime;
+
 pub mod book_structure;
+pub mod bundle;
 pub mod im_handling;
+pub mod latex;
+pub mod layouts;
+pub mod locale;
+pub mod page_format;
 mod pages;
 pub mod pdf_handling;
+pub mod profile;
+mod resize_cache;
+pub mod sections;
 pub mod specs;
+mod xmp;
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 enum LayoutReq {
     OnePortrait,
+    /// Alone on a full-bleed page
+    Hero(specs::HeroFit),
+    /// On the page of the folder spec of this index
+    Pinned(usize),
     Nothing,
 }
 
@@ -23,6 +38,9 @@ pub struct SourceImageInfo {
     path: PathBuf,
     dimensions: (u32, u32),
     orientation: Orientation,
+    date: NaiveDateTime,
+    camera: Option<String>,
+    caption: Option<latex::TexText>,
     user_req: LayoutReq,
 }
 
@@ -34,13 +52,19 @@ pub struct FolderInfo {
 
 #[derive(Debug)]
 pub struct ImageInfo {
+    /// Path of the resized copy
     pub path: PathBuf,
+    /// Path of the original image
+    pub source_path: PathBuf,
     resize_dims: (u32, u32),
     rotated_dims: (u32, u32),
+    date: NaiveDateTime,
+    caption: Option<latex::TexText>,
     user_req: LayoutReq,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(serde::Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
 pub enum PageOrientation {
     Portrait,
     Landscape,
@@ -58,7 +82,9 @@ impl std::fmt::Display for PageOrientation {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+/// Transformation to apply to an image to display it upright, from the EXIF
+/// orientation tag
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 enum Orientation {
     // Rotations are clockwise to match image crate
     Rotate90,
@@ -66,27 +92,78 @@ enum Orientation {
     Rotate180,
     Keep,
     Unknown,
-    Flipped,
+    /// Mirror along the vertical axis
+    FlipHorizontal,
+    /// Mirror along the horizontal axis
+    FlipVertical,
+    /// Mirror along the top-left to bottom-right diagonal
+    Transpose,
+    /// Mirror along the top-right to bottom-left diagonal
+    Transverse,
+}
+
+impl Orientation {
+    fn from_exif(value: u32) -> Orientation {
+        match value {
+            1 => Orientation::Keep,
+            2 => Orientation::FlipHorizontal,
+            3 => Orientation::Rotate180,
+            4 => Orientation::FlipVertical,
+            5 => Orientation::Transpose,
+            6 => Orientation::Rotate90,
+            7 => Orientation::Transverse,
+            8 => Orientation::Rotate270,
+            _ => Orientation::Unknown,
+        }
+    }
+
+    /// Whether this transformation swaps the width and the height
+    fn swaps_dims(self) -> bool {
+        matches!(
+            self,
+            Orientation::Rotate90
+                | Orientation::Rotate270
+                | Orientation::Transpose
+                | Orientation::Transverse
+        )
+    }
+
+    /// Dimensions of an image once this transformation is applied
+    fn rotated_dims(self, dims: (u32, u32)) -> (u32, u32) {
+        if self.swaps_dims() {
+            (dims.1, dims.0)
+        } else {
+            dims
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
 pub struct BookInfo<'a> {
-    pub title: &'a str,
+    pub title: &'a latex::TexText,
     pub title_font_size: &'a str,
     pub title_leading_size: &'a str,
     pub title_im_path: Option<&'a Path>,
+    pub language: locale::Language,
+    pub page_geometry: page_format::PageGeometry,
+    pub margins: profile::Margins,
 }
 
-#[derive(Debug)]
+#[derive(PartialEq, Eq, Debug)]
 enum PageKind {
-    TwoLandscapes,
-    OnePortrait,
+    /// Page of photos, with the name of its layout
+    Layout(String),
+    /// Full-bleed page of a single photo, without headings
+    Hero,
+    Filler,
 }
 
 #[derive(Debug)]
 pub struct PageInfo {
     path: PathBuf,
     kind: PageKind,
+    /// Images written for this page only, such as cropped copies of a photo
+    extra_files: Vec<PathBuf>,
 }
 
 fn replace(
@@ -99,27 +176,53 @@ fn replace(
     Ok(())
 }
 
-fn replace_path(
-    io_string: &mut String,
-    pat: &str,
-    im: &ImageInfo,
-    page_path: &Path,
-) {
-    if let Ok(can_path) = im.path.canonicalize() {
-        if let Some(im_path) = can_path.to_str() {
-            replace(io_string, pat, im_path).unwrap();
-        } else {
-            log::error!(
-                "could not include image path {:?} in {:?}: utf-8 failed",
-                im.path,
-                page_path,
-            );
-        }
-    } else {
+/// Path of a generated file as written in the LaTeX sources: relative to the
+/// output folder, where pdflatex is run, and with '/' separators, so that the
+/// output folder can be moved or built on another machine.
+fn tex_path(path: &Path, out_folder: &Path) -> std::io::Result<String> {
+    let rel_path = path.strip_prefix(out_folder).map_err(|_| {
         log::error!(
-            "could not include image path {:?} in {:?}: canonicalize failed",
-            im.path,
-            page_path,
+            "could not include path {:?}: not in output folder {:?}",
+            path,
+            out_folder,
+        );
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "outside path")
+    })?;
+    let components = rel_path
+        .components()
+        .filter(|c| c != &std::path::Component::CurDir)
+        .map(|c| {
+            c.as_os_str().to_str().ok_or_else(|| {
+                log::error!("could not include path {:?}: utf-8 failed", path);
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "utf-8 error",
+                )
+            })
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    Ok(components.join("/"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    #[test]
+    fn tex_path() {
+        let out_folder = Path::new("latex_album");
+        let im_path = out_folder.join("images/section_00/IMG_1.jpg");
+        assert_eq!(
+            super::tex_path(&im_path, out_folder).unwrap(),
+            "images/section_00/IMG_1.jpg",
+        );
+        let im_path = Path::new("./images/IMG_1.jpg");
+        assert_eq!(
+            super::tex_path(im_path, Path::new(".")).unwrap(),
+            "images/IMG_1.jpg",
+        );
+        assert!(
+            super::tex_path(Path::new("/tmp/IMG_1.jpg"), out_folder).is_err()
         );
     }
 }
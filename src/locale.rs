@@ -0,0 +1,311 @@
+//! Language dependent strings and date formatting
+use chrono::{Datelike, NaiveDate};
+
+/// Language of the album, matching a babel language
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Language {
+    English,
+    French,
+    German,
+    Italian,
+    Spanish,
+}
+
+impl std::str::FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "english" => Ok(Language::English),
+            "french" => Ok(Language::French),
+            "german" | "ngerman" => Ok(Language::German),
+            "italian" => Ok(Language::Italian),
+            "spanish" => Ok(Language::Spanish),
+            _ => Err(format!("unsupported language: {}", s)),
+        }
+    }
+}
+
+impl Language {
+    /// Option given to the babel package
+    pub fn babel_name(self) -> &'static str {
+        match self {
+            Language::English => "english",
+            Language::French => "french",
+            Language::German => "ngerman",
+            Language::Italian => "italian",
+            Language::Spanish => "spanish",
+        }
+    }
+
+    fn day_word(self) -> &'static str {
+        match self {
+            Language::English => "Day",
+            Language::French => "Jour",
+            Language::German => "Tag",
+            Language::Italian => "Giorno",
+            Language::Spanish => "Día",
+        }
+    }
+
+    /// Title of the filler pages left for notes
+    pub fn notes_title(self) -> &'static str {
+        match self {
+            Language::English | Language::French => "Notes",
+            Language::German => "Notizen",
+            Language::Italian => "Note",
+            Language::Spanish => "Notas",
+        }
+    }
+
+    fn month_name(self, month: u32) -> &'static str {
+        let names = match self {
+            Language::English => [
+                "January",
+                "February",
+                "March",
+                "April",
+                "May",
+                "June",
+                "July",
+                "August",
+                "September",
+                "October",
+                "November",
+                "December",
+            ],
+            Language::French => [
+                "janvier",
+                "février",
+                "mars",
+                "avril",
+                "mai",
+                "juin",
+                "juillet",
+                "août",
+                "septembre",
+                "octobre",
+                "novembre",
+                "décembre",
+            ],
+            Language::German => [
+                "Januar",
+                "Februar",
+                "März",
+                "April",
+                "Mai",
+                "Juni",
+                "Juli",
+                "August",
+                "September",
+                "Oktober",
+                "November",
+                "Dezember",
+            ],
+            Language::Italian => [
+                "gennaio",
+                "febbraio",
+                "marzo",
+                "aprile",
+                "maggio",
+                "giugno",
+                "luglio",
+                "agosto",
+                "settembre",
+                "ottobre",
+                "novembre",
+                "dicembre",
+            ],
+            Language::Spanish => [
+                "enero",
+                "febrero",
+                "marzo",
+                "abril",
+                "mayo",
+                "junio",
+                "julio",
+                "agosto",
+                "septiembre",
+                "octubre",
+                "noviembre",
+                "diciembre",
+            ],
+        };
+        names[month as usize - 1]
+    }
+
+    fn weekday_name(self, date: NaiveDate) -> &'static str {
+        let names = match self {
+            Language::English => [
+                "Monday",
+                "Tuesday",
+                "Wednesday",
+                "Thursday",
+                "Friday",
+                "Saturday",
+                "Sunday",
+            ],
+            Language::French => [
+                "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi",
+                "dimanche",
+            ],
+            Language::German => [
+                "Montag",
+                "Dienstag",
+                "Mittwoch",
+                "Donnerstag",
+                "Freitag",
+                "Samstag",
+                "Sonntag",
+            ],
+            Language::Italian => [
+                "lunedì",
+                "martedì",
+                "mercoledì",
+                "giovedì",
+                "venerdì",
+                "sabato",
+                "domenica",
+            ],
+            Language::Spanish => [
+                "lunes",
+                "martes",
+                "miércoles",
+                "jueves",
+                "viernes",
+                "sábado",
+                "domingo",
+            ],
+        };
+        names[date.weekday().num_days_from_monday() as usize]
+    }
+
+    /// Day of the month, as written before the month name
+    fn day_number(self, date: NaiveDate) -> String {
+        match (self, date.day()) {
+            (Language::French, 1) => "1er".to_string(),
+            (Language::German, day) => format!("{}.", day),
+            (_, day) => day.to_string(),
+        }
+    }
+
+    /// Word placed between the day and the month, and between the month and
+    /// the year
+    fn date_joiner(self) -> &'static str {
+        match self {
+            Language::Spanish => " de ",
+            _ => " ",
+        }
+    }
+
+    fn day_month(self, date: NaiveDate) -> String {
+        format!(
+            "{}{}{}",
+            self.day_number(date),
+            self.date_joiner(),
+            self.month_name(date.month()),
+        )
+    }
+
+    /// Full date, eg "9 August 2016"
+    pub fn format_date(self, date: NaiveDate) -> String {
+        format!(
+            "{}{}{}",
+            self.day_month(date),
+            self.date_joiner(),
+            date.year()
+        )
+    }
+
+    /// Date range, eg "9 – 15 August 2016" or "30 July – 2 August 2016"
+    pub fn format_date_range(
+        self,
+        first: NaiveDate,
+        last: NaiveDate,
+    ) -> String {
+        if first == last {
+            self.format_date(first)
+        } else if (first.year(), first.month()) == (last.year(), last.month()) {
+            format!("{} – {}", self.day_number(first), self.format_date(last))
+        } else if first.year() == last.year() {
+            format!("{} – {}", self.day_month(first), self.format_date(last))
+        } else {
+            format!("{} – {}", self.format_date(first), self.format_date(last))
+        }
+    }
+
+    /// Heading for the photos of a day of the trip, eg
+    /// "Day 3 — Tuesday 9 August"
+    pub fn format_day_heading(
+        self,
+        day_number: i64,
+        date: NaiveDate,
+    ) -> String {
+        let weekday_sep = match self {
+            Language::German => ", ",
+            _ => " ",
+        };
+        format!(
+            "{} {} — {}{}{}",
+            self.day_word(),
+            day_number,
+            self.weekday_name(date),
+            weekday_sep,
+            self.day_month(date),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use super::Language;
+
+    #[test]
+    fn format_date_range() {
+        let d = |y, m, d| NaiveDate::from_ymd(y, m, d);
+        let en = Language::English;
+        assert_eq!(
+            en.format_date_range(d(2016, 8, 9), d(2016, 8, 9)),
+            "9 August 2016",
+        );
+        assert_eq!(
+            en.format_date_range(d(2016, 8, 9), d(2016, 8, 15)),
+            "9 – 15 August 2016",
+        );
+        assert_eq!(
+            en.format_date_range(d(2016, 7, 30), d(2016, 8, 2)),
+            "30 July – 2 August 2016",
+        );
+        assert_eq!(
+            en.format_date_range(d(2016, 12, 30), d(2017, 1, 2)),
+            "30 December 2016 – 2 January 2017",
+        );
+        assert_eq!(
+            Language::French.format_date_range(d(2016, 8, 1), d(2016, 8, 3)),
+            "1er – 3 août 2016",
+        );
+    }
+
+    #[test]
+    fn format_day_heading() {
+        let date = NaiveDate::from_ymd(2016, 8, 9);
+        assert_eq!(
+            Language::English.format_day_heading(3, date),
+            "Day 3 — Tuesday 9 August",
+        );
+        assert_eq!(
+            Language::French.format_day_heading(3, date),
+            "Jour 3 — mardi 9 août",
+        );
+        assert_eq!(
+            Language::German.format_day_heading(3, date),
+            "Tag 3 — Dienstag, 9. August",
+        );
+        assert_eq!(
+            Language::Spanish.format_day_heading(3, date),
+            "Día 3 — martes 9 de agosto",
+        );
+    }
+}
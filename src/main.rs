@@ -1,9 +1,17 @@
 use std::error::Error;
 use std::path::Path;
 
-use phototex::book_structure;
-use phototex::im_handling;
+use phototex::book_structure::{self, LayoutOptions};
+use phototex::bundle;
+use phototex::im_handling::{self, FindOptions};
+use phototex::latex::TexText;
+use phototex::layouts;
+use phototex::locale::Language;
+use phototex::page_format::{self, PageGeometry};
 use phototex::pdf_handling;
+use phototex::profile;
+use phototex::sections::AutoSections;
+use phototex::specs::{HeroFit, MetadataFilter, SortOrder};
 use phototex::BookInfo;
 use phototex::PageOrientation;
 
@@ -49,6 +57,17 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .help("Title of the album. Defaults to \"\".")
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("title_latex")
+                .long("--title-latex")
+                .value_name("TITLE_LATEX")
+                .help(
+                    "Title of the album as raw LaTeX, to use formatting \
+                     commands. Replaces --title.",
+                )
+                .conflicts_with("title")
+                .takes_value(true),
+        )
         .arg(
             clap::Arg::with_name("title_font_size")
                 .long("--title-font-size")
@@ -69,7 +88,12 @@ fn main() -> Result<(), Box<dyn Error>> {
             clap::Arg::with_name("page_format")
                 .long("--page-format")
                 .value_name("PAGE_FORMAT")
-                .help("Page format. Currently supported values: 'A4' (default)")
+                .help(
+                    "Page format. Supported values: 'A3', 'A4' (default), \
+                     'A5', 'Letter', 'Legal' for portrait and landscape \
+                     pages, '21x21' (default) and '30x30' (in cm) for square \
+                     pages.",
+                )
                 .takes_value(true),
         )
         .arg(
@@ -77,11 +101,139 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .long("--page-orientation")
                 .value_name("PAGE_ORIENTATION")
                 .help(
-                    "Page orientation. Currently supported values: \
-                     'portrait' (default)",
+                    "Page orientation. Supported values: 'portrait' \
+                     (default), 'landscape', 'square'. The layouts of the \
+                     pages are chosen accordingly.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("page_size")
+                .long("--page-size")
+                .value_name("PAGE_SIZE")
+                .help(
+                    "Custom page size, as WIDTHxHEIGHTmm (eg '200x250mm'). \
+                     Replaces --page-format and --page-orientation.",
+                )
+                .conflicts_with_all(&["page_format", "page_orientation"])
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("bleed")
+                .long("--bleed")
+                .value_name("BLEED_MM")
+                .help(
+                    "Bleed added on each side of the pages, in mm, into which \
+                     the page background extends. Defaults to 0, or to the \
+                     bleed of the print shop profile.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("crop_marks")
+                .long("--crop-marks")
+                .help("Draw crop marks around the bleed.")
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name("sort_by")
+                .long("--sort-by")
+                .value_name("SORT_BY")
+                .help(
+                    "Order of the images and sections. Supported values: \
+                     'date' (default, uses EXIF creation date), 'name' \
+                     (file and folder names).",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("hero_fit")
+                .long("--hero-fit")
+                .value_name("HERO_FIT")
+                .help(
+                    "How the hero photos of the specs fill pages of another \
+                     orientation: 'crop' (default) to fill the width of the \
+                     page, or 'rotate' by a quarter turn.",
+                )
+                .possible_values(&["crop", "rotate"])
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("min_rating")
+                .long("--min-rating")
+                .value_name("NB_STARS")
+                .help(
+                    "Leave out the images rated below NB_STARS in their XMP \
+                     metadata, from a sidecar file or embedded in the image.",
                 )
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("tag")
+                .long("--tag")
+                .value_name("TAG")
+                .help(
+                    "Keep only the images with this tag in their XMP \
+                     metadata. Can be repeated, to keep the images with one \
+                     of the tags.",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("exclude_tag")
+                .long("--exclude-tag")
+                .value_name("TAG")
+                .help(
+                    "Leave out the images with this tag in their XMP \
+                     metadata. Can be repeated.",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("lenient")
+                .long("--lenient")
+                .help(
+                    "Report the errors of the specs.json files as warnings, \
+                     ignoring the files that cannot be read, instead of \
+                     stopping.",
+                )
+                .takes_value(false),
+        )
+        .arg(
+            clap::Arg::with_name("auto_sections")
+                .long("--auto-sections")
+                .value_name("AUTO_SECTIONS")
+                .help(
+                    "Split a flat folder of images into sections, either one \
+                     per day ('day') or whenever two photos are further \
+                     apart than a gap given as HH:MM (eg '06:00').",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("language")
+                .long("--language")
+                .value_name("LANGUAGE")
+                .help(
+                    "Language of the album, as a babel language. Supported \
+                     values: 'french' (default), 'english', 'german', \
+                     'italian', 'spanish'.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("day_headings")
+                .long("--day-headings")
+                .help(
+                    "Start a new page for each day of photos, with a heading \
+                     such as \"Day 3 — Tuesday 9 August\".",
+                )
+                .takes_value(false),
+        )
         .arg(
             clap::Arg::with_name("strip_inner_covers")
                 .long("--strip-inner-covers")
@@ -92,6 +244,91 @@ fn main() -> Result<(), Box<dyn Error>> {
                 )
                 .takes_value(false),
         )
+        .arg(
+            clap::Arg::with_name("profile")
+                .long("--profile")
+                .value_name("PROFILE")
+                .help(
+                    "Print shop profile, setting the page size, margins, \
+                     page count and cover requirements, colours and name of \
+                     the uploaded PDF. Builtin profiles: 'home', \
+                     'flexilivre', 'square-30'. Replaces --page-format, \
+                     --page-orientation and --page-size.",
+                )
+                .conflicts_with_all(&[
+                    "page_format",
+                    "page_orientation",
+                    "page_size",
+                ])
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("profiles")
+                .long("--profiles")
+                .value_name("PROFILES_FILE")
+                .help(
+                    "JSON file defining additional print shop profiles, \
+                     looked up before the builtin ones.",
+                )
+                .requires("profile")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("order_window")
+                .long("--order-window")
+                .value_name("NB_PHOTOS")
+                .help(
+                    "Number of photos, from 0 to 3, that a page may skip \
+                     over to group photos that are not next to each other. \
+                     Defaults to 0, which keeps the order of the photos.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("layouts")
+                .long("--layouts")
+                .value_name("LAYOUTS_FILE")
+                .help(
+                    "JSON file of page layouts, chosen from along with the \
+                     builtin ones.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("target_pages")
+                .long("--target-pages")
+                .value_name("NB_PAGES")
+                .help(
+                    "Number of pages of the PDF, covers included. Denser or \
+                     sparser layouts are used to reach it.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("filler")
+                .long("--filler")
+                .value_name("FILLER")
+                .help(
+                    "Pages added at the end of the album to meet the page \
+                     count rules of the print shop profile: 'blank', 'notes' \
+                     or 'mosaic'. Replaces the filler of the profile.",
+                )
+                .possible_values(&["blank", "notes", "mosaic"])
+                .requires("profile")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("bundle")
+                .long("--bundle")
+                .value_name("BUNDLE")
+                .help(
+                    "Instead of compiling the album, write a self-contained \
+                     copy of the LaTeX project, that can be built on another \
+                     machine. It is a zip archive if BUNDLE ends with '.zip', \
+                     and a directory otherwise.",
+                )
+                .takes_value(true),
+        )
         .arg(
             clap::Arg::with_name("verbosity")
                 .short("v")
@@ -115,22 +352,109 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let dpm = matches.value_of("dpm").unwrap_or("12.").parse()?;
 
-    let page_format = matches.value_of("page_format").unwrap_or("A4");
-    let page_orientation =
-        matches.value_of("page_orientation").unwrap_or("portrait");
-    let page_orientation = match page_orientation {
-        "portrait" => PageOrientation::Portrait,
-        "landscape" => PageOrientation::Landscape,
-        "square" => PageOrientation::Square,
-        _ => {
-            log::error!("unsupported page orientation: {}", page_orientation);
-            std::process::exit(1);
+    let profile = matches
+        .value_of("profile")
+        .map(|name| {
+            let user_profiles = matches.value_of("profiles").map(Path::new);
+            profile::find_profile(name, user_profiles)
+        })
+        .transpose()?;
+
+    let page_size = matches.value_of("page_size");
+    let page_dims = match (&profile, page_size) {
+        (Some(profile), _) => profile.page_dims()?.0,
+        (None, Some(page_size)) => page_format::parse_page_size(page_size)?,
+        (None, None) => {
+            let page_orientation =
+                matches.value_of("page_orientation").unwrap_or("portrait");
+            let page_orientation = match page_orientation {
+                "portrait" => PageOrientation::Portrait,
+                "landscape" => PageOrientation::Landscape,
+                "square" => PageOrientation::Square,
+                _ => {
+                    log::error!(
+                        "unsupported page orientation: {}",
+                        page_orientation
+                    );
+                    std::process::exit(1);
+                }
+            };
+            let page_format = matches.value_of("page_format").unwrap_or(
+                match page_orientation {
+                    PageOrientation::Square => "21x21",
+                    _ => "A4",
+                },
+            );
+            page_format::page_dims(page_format, page_orientation)?
         }
     };
 
-    let strip_inner_covers = matches.is_present("strip_inner_covers");
+    let sort_order: SortOrder =
+        matches.value_of("sort_by").unwrap_or("date").parse()?;
+    let hero_fit: HeroFit =
+        matches.value_of("hero_fit").unwrap_or("crop").parse()?;
+
+    let tags = |name| {
+        matches
+            .values_of(name)
+            .map(|tags| tags.map(str::to_string).collect())
+    };
+    let metadata_filter = MetadataFilter {
+        min_rating: matches
+            .value_of("min_rating")
+            .map(str::parse)
+            .transpose()?,
+        tags: tags("tag"),
+        exclude_tags: tags("exclude_tag"),
+    };
 
-    let title = matches.value_of("title").unwrap_or("");
+    let auto_sections: Option<AutoSections> = matches
+        .value_of("auto_sections")
+        .map(str::parse)
+        .transpose()?;
+
+    let language: Language =
+        matches.value_of("language").unwrap_or("french").parse()?;
+
+    let day_headings = matches.is_present("day_headings");
+    let order_window: usize =
+        matches.value_of("order_window").unwrap_or("0").parse()?;
+    if order_window > book_structure::MAX_ORDER_WINDOW {
+        return Err(format!(
+            "the order window cannot be more than {}",
+            book_structure::MAX_ORDER_WINDOW
+        )
+        .into());
+    }
+    let layouts =
+        layouts::load_layouts(matches.value_of("layouts").map(Path::new))?;
+    let target_pages = matches
+        .value_of("target_pages")
+        .map(str::parse::<usize>)
+        .transpose()?;
+
+    let strip_inner_covers = matches.is_present("strip_inner_covers")
+        || profile.as_ref().is_some_and(|p| p.strip_inner_covers);
+
+    let bleed = match matches.value_of("bleed") {
+        Some(bleed) => page_format::parse_bleed(bleed)?,
+        None => profile.as_ref().map_or(0., |p| p.bleed),
+    };
+    let page_geometry = PageGeometry {
+        trim_dims: page_dims,
+        bleed,
+        crop_marks: matches.is_present("crop_marks")
+            || profile.as_ref().is_some_and(|p| p.crop_marks),
+    };
+    let margins = profile.as_ref().map(|p| p.margins).unwrap_or_default();
+    let color = profile.as_ref().map(|p| p.color).unwrap_or_default();
+
+    let bundle_path = matches.value_of("bundle").map(Path::new);
+
+    let title = match matches.value_of("title_latex") {
+        Some(latex) => TexText::latex(latex),
+        None => TexText::plain(matches.value_of("title").unwrap_or("")),
+    };
 
     let title_font_size: f32 = matches
         .value_of("title_font_size")
@@ -151,26 +475,40 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     log::info!("Using images path: {}", images);
 
-    let folder_infos = im_handling::find_images(images, im_ext);
-    let page_dims = match (page_format, page_orientation) {
-        ("A4", PageOrientation::Portrait) => (210., 297.),
-        _ => {
-            log::error!(
-                "unsupported page format/orientation: {}/{}",
-                page_format,
-                page_orientation,
-            );
-            std::process::exit(1);
-        }
+    let find_options = FindOptions {
+        im_ext,
+        sort_order,
+        auto_sections,
+        language,
+        hero_fit,
+        metadata_filter: &metadata_filter,
+        lenient: matches.is_present("lenient"),
     };
+    let (folder_infos, filtered) =
+        im_handling::find_images(images, find_options)?;
+    if !filtered.is_empty() {
+        println!("Filtered images:");
+        for summary in &filtered {
+            println!("  {}", summary);
+        }
+    }
+    println!("Detected cameras:");
+    for summary in im_handling::camera_summaries(&folder_infos) {
+        println!("  {}", summary);
+    }
     let images_path = out_folder.join("images");
     std::fs::create_dir_all(&images_path)?;
-    let folder_infos =
-        im_handling::resize_images(folder_infos, dpm, page_dims, &images_path)?;
+    let folder_infos = im_handling::resize_images(
+        folder_infos,
+        dpm,
+        page_dims,
+        color,
+        &images_path,
+    )?;
     let title_im_path = title_im_name.and_then(|name| {
         for im_info_folder in &folder_infos {
             for im_info in &im_info_folder.image_infos {
-                if im_info.path.ends_with(name) {
+                if im_info.source_path.ends_with(name) {
                     return Some(im_info.path.as_path());
                 }
             }
@@ -178,25 +516,89 @@ fn main() -> Result<(), Box<dyn Error>> {
         None
     });
 
-    let page_infos = book_structure::write_pages(out_folder, &folder_infos)?;
+    let layout_options = LayoutOptions {
+        day_headings,
+        language,
+        text_dims: margins.text_dims(page_dims),
+        order_window,
+        layouts: &layouts,
+        page_geometry,
+        target_pages,
+        strip_inner_covers,
+    };
+    let (mut page_infos, target_report) =
+        book_structure::write_pages(out_folder, &folder_infos, layout_options)?;
+    if let Some(report) = target_report {
+        println!("Page count: {}", report);
+    }
+    if let Some(profile) = &profile {
+        let filler = match matches.value_of("filler") {
+            Some(filler) => filler.parse()?,
+            None => profile.filler,
+        };
+        let report = book_structure::add_filler_pages(
+            out_folder,
+            &mut page_infos,
+            &folder_infos,
+            profile,
+            strip_inner_covers,
+            filler,
+            language,
+        )?;
+        println!("Page count: {}", report);
+    }
     let book_info = BookInfo {
-        title,
+        title: &title,
         title_font_size: &title_font_size,
         title_leading_size: &title_leading_size,
         title_im_path,
+        language,
+        page_geometry,
+        margins,
     };
     let top_file_name =
         book_structure::write_toplevel(out_folder, book_info, &page_infos)?;
 
+    if let Some(bundle_path) = bundle_path {
+        if strip_inner_covers {
+            log::warn!("--strip-inner-covers is ignored with --bundle");
+        }
+        bundle::write_bundle(
+            out_folder,
+            bundle_path,
+            &top_file_name,
+            &page_infos,
+            &folder_infos,
+        )?;
+        log::info!("Bundle written to {}", bundle_path.display());
+        return Ok(());
+    }
+
     let pdf_file_name = pdf_handling::generate_pdf(out_folder, &top_file_name)?;
+    pdf_handling::set_page_boxes(out_folder, &pdf_file_name, &page_geometry)?;
+    let mut upload_file_name = pdf_file_name.clone();
     if strip_inner_covers {
         log::info!("Stripping inner covers...");
         let trimmed_pdf_file_name = pdf_handling::remove_second_third_covers(
             out_folder,
             &pdf_file_name,
-            page_infos.len()
+            page_infos.len(),
         )?;
         log::info!("Stripping done, in {}", trimmed_pdf_file_name);
+        upload_file_name = trimmed_pdf_file_name;
+    }
+    if let Some(profile) = profile {
+        let nb_pages = pdf_handling::page_count(out_folder, &upload_file_name)?;
+        if let Err(e) = profile.check_page_count(nb_pages) {
+            log::warn!("the album does not meet the print shop rules: {}", e);
+        }
+        let output_file_name =
+            profile.output_file_name(&title.plain_text(), nb_pages);
+        std::fs::copy(
+            out_folder.join(&upload_file_name),
+            out_folder.join(&output_file_name),
+        )?;
+        log::info!("PDF to upload: {}", output_file_name);
     }
     Ok(())
 }
@@ -0,0 +1,214 @@
+//! Paper sizes of the album pages
+use crate::PageOrientation;
+
+/// Known page formats, with their width and height in mm, as portrait for
+/// rectangular ones
+const PAGE_FORMATS: &[(&str, (f32, f32))] = &[
+    ("A3", (297., 420.)),
+    ("A4", (210., 297.)),
+    ("A5", (148., 210.)),
+    ("Letter", (215.9, 279.4)),
+    ("Legal", (215.9, 355.6)),
+    ("21x21", (210., 210.)),
+    ("30x30", (300., 300.)),
+];
+
+/// Names of the known page formats
+pub fn format_names() -> impl Iterator<Item = &'static str> {
+    PAGE_FORMATS.iter().map(|(name, _)| *name)
+}
+
+/// Page dimensions in mm of a known format, for the given orientation
+pub fn page_dims(
+    format: &str,
+    orientation: PageOrientation,
+) -> Result<(f32, f32), String> {
+    let (width, height) = PAGE_FORMATS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(format))
+        .map(|(_, dims)| *dims)
+        .ok_or_else(|| {
+            format!(
+                "unknown page format {}, supported formats are: {}",
+                format,
+                format_names().collect::<Vec<_>>().join(", "),
+            )
+        })?;
+    let square = width == height;
+    match orientation {
+        PageOrientation::Portrait if !square => Ok((width, height)),
+        PageOrientation::Landscape if !square => Ok((height, width)),
+        PageOrientation::Square if square => Ok((width, height)),
+        _ => Err(format!(
+            "page format {} cannot be used with {} orientation",
+            format, orientation,
+        )),
+    }
+}
+
+/// Parse a custom page size given as `WxHmm`, eg `200x250mm`
+pub fn parse_page_size(size: &str) -> Result<(f32, f32), String> {
+    let dims = size.trim_end_matches("mm");
+    let parse_dim = |dim: &str| {
+        dim.trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|dim| dim.is_finite() && *dim > 0.)
+    };
+    let mut dims = dims.split('x').map(parse_dim);
+    match (dims.next(), dims.next(), dims.next()) {
+        (Some(Some(width)), Some(Some(height)), None) => Ok((width, height)),
+        _ => Err(format!(
+            "invalid page size: {}, expected WIDTHxHEIGHTmm, eg 200x250mm",
+            size
+        )),
+    }
+}
+
+/// Check a bleed in mm, which may be 0 but not negative
+pub fn check_bleed(bleed: f32) -> Result<f32, String> {
+    if bleed.is_finite() && bleed >= 0. {
+        Ok(bleed)
+    } else {
+        Err(format!(
+            "invalid bleed: {}, expected a number of mm, eg 3",
+            bleed
+        ))
+    }
+}
+
+/// Parse a bleed given in mm, eg `3`
+pub fn parse_bleed(bleed: &str) -> Result<f32, String> {
+    bleed
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| {
+            format!("invalid bleed: {}, expected a number of mm, eg 3", bleed)
+        })
+        .and_then(check_bleed)
+}
+
+/// Space left around the bleed for the crop marks, in mm
+const CROP_MARKS_SPACE: f32 = 10.;
+
+/// Size of the printed sheets: the pages, the bleed around them that is cut
+/// away after printing, and optionally the space for the crop marks
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PageGeometry {
+    /// Width and height of the pages once trimmed, in mm
+    pub trim_dims: (f32, f32),
+    /// Bleed on each side of the pages, in mm
+    pub bleed: f32,
+    pub crop_marks: bool,
+}
+
+/// A rectangle on the sheet, as left, bottom, right and top coordinates in
+/// mm from its lower left corner, as PDF boxes
+pub type PageBox = (f32, f32, f32, f32);
+
+impl PageGeometry {
+    /// Space around the bleed, holding the crop marks
+    pub fn marks_space(&self) -> f32 {
+        if self.crop_marks {
+            CROP_MARKS_SPACE
+        } else {
+            0.
+        }
+    }
+
+    /// Width and height of the whole sheet, in mm
+    pub fn paper_dims(&self) -> (f32, f32) {
+        let around = 2. * (self.bleed + self.marks_space());
+        (self.trim_dims.0 + around, self.trim_dims.1 + around)
+    }
+
+    pub fn bleed_box(&self) -> PageBox {
+        let (width, height) = self.paper_dims();
+        let space = self.marks_space();
+        (space, space, width - space, height - space)
+    }
+
+    pub fn trim_box(&self) -> PageBox {
+        let (width, height) = self.paper_dims();
+        let space = self.marks_space() + self.bleed;
+        (space, space, width - space, height - space)
+    }
+}
+
+/// Orientation of a page of the given dimensions
+pub fn page_orientation((width, height): (f32, f32)) -> PageOrientation {
+    if width > height {
+        PageOrientation::Landscape
+    } else if width < height {
+        PageOrientation::Portrait
+    } else {
+        PageOrientation::Square
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        page_dims, page_orientation, parse_bleed, parse_page_size, PageGeometry,
+    };
+    use crate::PageOrientation;
+
+    #[test]
+    fn known_formats() {
+        assert_eq!(
+            page_dims("A5", PageOrientation::Landscape),
+            Ok((210., 148.))
+        );
+        assert_eq!(
+            page_dims("letter", PageOrientation::Portrait),
+            Ok((215.9, 279.4))
+        );
+        assert_eq!(
+            page_dims("30x30", PageOrientation::Square),
+            Ok((300., 300.))
+        );
+        assert!(page_dims("A4", PageOrientation::Square).is_err());
+        assert!(page_dims("30x30", PageOrientation::Portrait).is_err());
+        assert!(page_dims("B5", PageOrientation::Portrait).is_err());
+    }
+
+    #[test]
+    fn custom_sizes() {
+        assert_eq!(parse_page_size("200x250mm"), Ok((200., 250.)));
+        assert_eq!(parse_page_size("215.9x279.4"), Ok((215.9, 279.4)));
+        assert!(parse_page_size("200mm").is_err());
+        assert!(parse_page_size("0x250mm").is_err());
+        assert!(parse_page_size("200x250x3mm").is_err());
+        assert_eq!(page_orientation((250., 200.)), PageOrientation::Landscape);
+        assert_eq!(page_orientation((200., 200.)), PageOrientation::Square);
+    }
+
+    #[test]
+    fn bleeds() {
+        assert_eq!(parse_bleed("3"), Ok(3.));
+        assert_eq!(parse_bleed("0"), Ok(0.));
+        assert!(parse_bleed("-3").is_err());
+        assert!(parse_bleed("NaN").is_err());
+        assert!(parse_bleed("inf").is_err());
+        assert!(parse_bleed("3mm").is_err());
+    }
+
+    #[test]
+    fn page_geometry() {
+        let geometry = PageGeometry {
+            trim_dims: (210., 297.),
+            bleed: 3.,
+            crop_marks: false,
+        };
+        assert_eq!(geometry.paper_dims(), (216., 303.));
+        assert_eq!(geometry.bleed_box(), (0., 0., 216., 303.));
+        assert_eq!(geometry.trim_box(), (3., 3., 213., 300.));
+        let geometry = PageGeometry {
+            crop_marks: true,
+            ..geometry
+        };
+        assert_eq!(geometry.paper_dims(), (236., 323.));
+        assert_eq!(geometry.bleed_box(), (10., 10., 226., 313.));
+        assert_eq!(geometry.trim_box(), (13., 13., 223., 310.));
+    }
+}
@@ -1,17 +1,25 @@
-use std::io::{Read, Write, BufReader, BufWriter};
+//! This module contains functions to write pages with various layouts
 use std::fs::File;
-///! This module contains functions to write pages with various layouts
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 
-use crate::{replace, replace_path, ImageInfo, PageInfo, PageKind};
+use crate::im_handling::{crop_to_page, split_in_halves};
+use crate::latex::{self, TexText};
+use crate::layouts::{CaptionPosition, Layout, Slot, PHOTO_AREA_HEIGHT};
+use crate::page_format::PageBox;
+use crate::specs::HeroFit;
+use crate::{replace, tex_path, ImageInfo, PageInfo, PageKind};
 
 #[derive(Debug)]
 pub struct Page {
     path: PathBuf,
+    out_folder: PathBuf,
 }
 
-pub(crate) fn set_section_title(
-    page_info: &PageInfo, title: Option<&str>
+pub(crate) fn set_page_headers(
+    page_info: &PageInfo,
+    title: Option<&TexText>,
+    day_heading: Option<&str>,
 ) -> std::io::Result<()> {
     let file = File::open(&page_info.path)?;
     let mut buf_reader = BufReader::new(file);
@@ -21,9 +29,13 @@ pub(crate) fn set_section_title(
     replace(
         &mut page_text,
         "PHOTOTEX_PAGE_TITLE",
-        title.unwrap_or(comment),
+        &title.map_or(comment.to_string(), TexText::to_latex),
     )
     .unwrap();
+    let day_heading = day_heading.map_or("%".to_string(), |heading| {
+        format!("{{\\large {}}}", latex::escape(heading))
+    });
+    replace(&mut page_text, "PHOTOTEX_DAY_HEADING", &day_heading).unwrap();
 
     let f = File::create(&page_info.path)?;
     let mut writer = BufWriter::new(f);
@@ -31,189 +43,283 @@ pub(crate) fn set_section_title(
     Ok(())
 }
 
+/// LaTeX length for a fraction of the text width or height
+fn length(fraction: f32, dimension: &str) -> String {
+    format!("{:.4}\\{}", fraction, dimension)
+}
+
+/// Horizontal alignment of a photo in its slot
+#[derive(Copy, Clone)]
+enum Align {
+    Center,
+    /// Against the right side, for the left half of a spread
+    Right,
+    /// Against the left side, for the right half of a spread
+    Left,
+}
+
+/// Box holding the photo of a slot and its caption, placed from the top left
+/// corner of the photo area
+fn slot_box(
+    slot: &Slot,
+    image_path: &str,
+    caption: Option<&TexText>,
+    align: Align,
+) -> String {
+    let (left, top, width, height) = slot.rect;
+    let (photo_width, photo_height) = slot.photo_rect();
+    let area_height =
+        |fraction| length(fraction * PHOTO_AREA_HEIGHT, "textheight");
+    let image = format!(
+        "      \\includegraphics[\n        \
+         width={},\n        \
+         height={},\n        \
+         keepaspectratio\n      \
+         ]{{{}}}",
+        length(photo_width, "textwidth"),
+        area_height(photo_height),
+        image_path,
+    );
+    let contents = match (slot.caption, caption) {
+        (CaptionPosition::Below, Some(caption)) => {
+            format!("{}\\\\[0.5em]\n      {}\n", image, caption.to_latex())
+        }
+        (CaptionPosition::Above, Some(caption)) => {
+            format!("      {}\\\\[0.5em]\n{}\n", caption.to_latex(), image)
+        }
+        _ => format!("{}\n", image),
+    };
+    // the halves of a spread are aligned on the side of the photo, since
+    // only one of them has the caption
+    let (position, align) = match (align, slot.caption) {
+        (Align::Center, _) => ('c', "\\centering"),
+        (Align::Right, CaptionPosition::Above) => ('b', "\\raggedleft"),
+        (Align::Right, _) => ('t', "\\raggedleft"),
+        (Align::Left, CaptionPosition::Above) => ('b', "\\raggedright"),
+        (Align::Left, _) => ('t', "\\raggedright"),
+    };
+    format!(
+        "  % {}\n  \
+         \\makebox[0pt][l]{{\\hspace*{{{}}}\\raisebox{{-{}}}[0pt][0pt]{{%\n    \
+         \\begin{{minipage}}[t][{}][{}]{{{}}}\n      \
+         \\vspace{{0pt}}{}\n\
+         {}    \
+         \\end{{minipage}}}}}}%\n",
+        slot.name,
+        length(left, "textwidth"),
+        area_height(top),
+        area_height(height),
+        position,
+        length(width, "textwidth"),
+        align,
+        contents,
+    )
+}
+
 impl Page {
-    pub fn new(
-        page_id: &mut usize,
-        out_folder: &Path,
-    ) -> Page {
+    pub fn new(page_id: &mut usize, out_folder: &Path) -> Page {
         let path = out_folder.join(format!("page{:03}", *page_id));
         *page_id += 1;
         Page {
             path,
+            out_folder: out_folder.to_path_buf(),
         }
     }
 
-    pub(crate) fn write_two_landscapes(
+    /// Filler page, added after the pages of photos
+    pub fn filler(filler_id: usize, out_folder: &Path) -> Page {
+        Page {
+            path: out_folder.join(format!("filler{:03}", filler_id)),
+            out_folder: out_folder.to_path_buf(),
+        }
+    }
+
+    fn write_page(
         self,
-        im0: &ImageInfo,
-        im1: &ImageInfo,
+        page_text: &str,
+        kind: PageKind,
     ) -> std::io::Result<PageInfo> {
         let page_path = &self.path;
         std::fs::create_dir_all(page_path)?;
         let page_path = page_path.join("page.tex");
         let f = File::create(&page_path)?;
         let mut writer = BufWriter::new(f);
-        let mut page_text =
-            include_str!("../data/page_2_landscapes.tex").to_string();
-        if let Some(im0_path) = im0.path.canonicalize()?.to_str() {
-            replace(&mut page_text, "PHOTOTEX_FIRST_IMAGE_PATH", im0_path)
-                .unwrap();
-        } else {
-            log::error!(
-                "could not include image path {:?} in {:?}: utf-8 failed",
-                im0.path,
-                page_path,
-            );
-        }
-        if let Some(im1_path) = im1.path.canonicalize()?.to_str() {
-            replace(&mut page_text, "PHOTOTEX_SECOND_IMAGE_PATH", im1_path)
-                .unwrap();
-        } else {
-            log::error!(
-                "could not include image path {:?} in {:?}: utf-8 failed",
-                im1.path,
-                page_path,
-            );
-        }
-        replace(&mut page_text, "PHOTOTEX_FIRST_LEGEND", "%").unwrap();
-        replace(&mut page_text, "PHOTOTEX_SECOND_LEGEND", "%").unwrap();
         write!(writer, "{}", page_text)?;
 
         Ok(PageInfo {
             path: page_path,
-            kind: PageKind::TwoLandscapes,
+            kind,
+            extra_files: Vec::new(),
         })
     }
 
-    pub(crate) fn write_two_portraits_one_landscape(
+    fn write_filler(self, page_text: &str) -> std::io::Result<PageInfo> {
+        self.write_page(page_text, PageKind::Filler)
+    }
+
+    pub(crate) fn write_blank(self) -> std::io::Result<PageInfo> {
+        self.write_filler(include_str!("../data/page_blank.tex"))
+    }
+
+    pub(crate) fn write_notes(self, title: &str) -> std::io::Result<PageInfo> {
+        let mut page_text = include_str!("../data/page_notes.tex").to_string();
+        replace(
+            &mut page_text,
+            "PHOTOTEX_NOTES_TITLE",
+            &latex::escape(title),
+        )
+        .unwrap();
+        self.write_filler(&page_text)
+    }
+
+    /// Page showing small versions of `images`, four per row
+    pub(crate) fn write_mosaic(
         self,
-        im0: &ImageInfo,
-        im1: &ImageInfo,
-        im2: &ImageInfo,
+        images: &[&ImageInfo],
     ) -> std::io::Result<PageInfo> {
-        let page_path = &self.path;
-        std::fs::create_dir_all(page_path)?;
-        let page_path = page_path.join("page.tex");
-        let f = File::create(&page_path)?;
-        let mut writer = BufWriter::new(f);
-        let mut page_text =
-            include_str!("../data/page_2_portrait_1_landscape.tex").to_string();
-        let (im0_, im1_, im2_);
-        if im0.rotated_dims.0 >= im0.rotated_dims.1 {
-            im2_ = im0;
-            im0_ = im1;
-            im1_ = im2;
-        } else if im1.rotated_dims.0 >= im1.rotated_dims.1 {
-            im2_ = im1;
-            im0_ = im0;
-            im1_ = im2;
-        } else {
-            im2_ = im2;
-            im0_ = im0;
-            im1_ = im1;
+        let mut page_text = include_str!("../data/page_mosaic.tex").to_string();
+        let mut mosaic = String::new();
+        for (nb, im) in images.iter().enumerate() {
+            let separator = if nb % 4 == 3 {
+                "\\\\[0.5em]"
+            } else {
+                "\\hfill"
+            };
+            mosaic.push_str(&format!(
+                "    \\includegraphics[\n      \
+                 width=0.23\\textwidth,\n      \
+                 height=0.17\\textheight,\n      \
+                 keepaspectratio\n    \
+                 ]{{{}}}{}\n",
+                tex_path(&im.path, &self.out_folder)?,
+                separator,
+            ));
         }
-        replace_path(
-            &mut page_text,
-            "PHOTOTEX_FIRST_IMAGE_PATH",
-            im0_,
-            &page_path,
-        );
-        replace_path(
-            &mut page_text,
-            "PHOTOTEX_SECOND_IMAGE_PATH",
-            im1_,
-            &page_path,
-        );
-        replace_path(
-            &mut page_text,
-            "PHOTOTEX_THIRD_IMAGE_PATH",
-            im2_,
-            &page_path,
-        );
-        replace(&mut page_text, "PHOTOTEX_FIRST_SECOND_LEGENDS", "%").unwrap();
-        replace(&mut page_text, "PHOTOTEX_THIRD_LEGEND", "%").unwrap();
-        write!(writer, "{}", page_text)?;
-
-        Ok(PageInfo {
-            path: page_path,
-            kind: PageKind::TwoLandscapes,
-        })
+        replace(&mut page_text, "PHOTOTEX_MOSAIC_IMAGES", &mosaic).unwrap();
+        self.write_filler(&page_text)
     }
 
-    pub(crate) fn write_four_portraits(
+    fn write_slots(
         self,
-        im0: &ImageInfo,
-        im1: &ImageInfo,
-        im2: &ImageInfo,
-        im3: &ImageInfo,
+        layout: &Layout,
+        slots: &str,
     ) -> std::io::Result<PageInfo> {
-        let page_path = &self.path;
-        std::fs::create_dir_all(page_path)?;
-        let page_path = page_path.join("page.tex");
-        let f = File::create(&page_path)?;
-        let mut writer = BufWriter::new(f);
-        let mut page_text =
-            include_str!("../data/page_4_portraits.tex").to_string();
-        replace_path(
-            &mut page_text,
-            "PHOTOTEX_FIRST_IMAGE_PATH",
-            &im0,
-            &page_path,
-        );
-        replace_path(
+        let mut page_text = include_str!("../data/page_layout.tex").to_string();
+        replace(
             &mut page_text,
-            "PHOTOTEX_SECOND_IMAGE_PATH",
-            &im1,
-            &page_path,
-        );
-        replace_path(
-            &mut page_text,
-            "PHOTOTEX_THIRD_IMAGE_PATH",
-            &im2,
-            &page_path,
-        );
-        replace_path(
-            &mut page_text,
-            "PHOTOTEX_FOURTH_IMAGE_PATH",
-            &im3,
-            &page_path,
-        );
-        replace(&mut page_text, "PHOTOTEX_FIRST_SECOND_LEGENDS", "%").unwrap();
-        replace(&mut page_text, "PHOTOTEX_THIRD_FOURTH_LEGENDS", "%").unwrap();
-        write!(writer, "{}", page_text)?;
+            "PHOTOTEX_PHOTO_AREA_HEIGHT",
+            &length(PHOTO_AREA_HEIGHT, "textheight"),
+        )
+        .unwrap();
+        replace(&mut page_text, "PHOTOTEX_SLOTS", slots).unwrap();
+        let kind = PageKind::Layout(layout.name.clone());
+        self.write_page(&page_text, kind)
+    }
 
-        Ok(PageInfo {
-            path: page_path,
-            kind: PageKind::TwoLandscapes,
-        })
+    /// Page of photos following a layout, the images being given in the
+    /// order of its slots
+    pub(crate) fn write_layout(
+        self,
+        layout: &Layout,
+        images: &[&ImageInfo],
+    ) -> std::io::Result<PageInfo> {
+        let mut slots = String::new();
+        for (slot, im) in layout.slots.iter().zip(images) {
+            let image_path = tex_path(&im.path, &self.out_folder)?;
+            slots.push_str(&slot_box(
+                slot,
+                &image_path,
+                im.caption.as_ref(),
+                Align::Center,
+            ));
+        }
+        self.write_slots(layout, &slots)
     }
 
-    pub(crate) fn write_one_portrait(
+    /// Full-bleed page of a single photo, centered on the bleed box
+    /// `bleed_box`. The photo fills the width of the page, cropped where it
+    /// overflows its height, or is turned and kept whole when `fit` asks for
+    /// it.
+    pub(crate) fn write_hero(
         self,
-        im_info: &ImageInfo,
+        im: &ImageInfo,
+        fit: HeroFit,
+        bleed_box: PageBox,
     ) -> std::io::Result<PageInfo> {
-        let page_path = &self.path;
-        std::fs::create_dir_all(page_path)?;
-        let page_path = page_path.join("page.tex");
-        let f = File::create(&page_path)?;
-        let mut writer = BufWriter::new(f);
-        let mut page_text =
-            include_str!("../data/page_1_portrait.tex").to_string();
-        if let Some(im_path) = im_info.path.canonicalize()?.to_str() {
-            replace(&mut page_text, "PHOTOTEX_IMAGE_PATH", im_path).unwrap();
-        } else {
-            log::error!(
-                "could not include image path {:?} in {:?}: utf-8 failed",
-                im_info.path,
-                page_path,
-            );
+        let (left, bottom, right, top) = bleed_box;
+        let page_dims = (right - left, top - bottom);
+        let (width, height) = im.rotated_dims;
+        let rotate = fit == HeroFit::Rotate
+            && (width > height) != (page_dims.0 > page_dims.1)
+            && page_dims.0 != page_dims.1;
+        let (hero_path, (width, height)) =
+            crop_to_page(&im.path, page_dims, fit, rotate)
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+        // the photo is as large as the page lets it be, without cropping
+        let scale =
+            (page_dims.0 / width as f32).min(page_dims.1 / height as f32);
+        let hero_dims = (width as f32 * scale, height as f32 * scale);
+        let hero_left = left + (page_dims.0 - hero_dims.0) / 2.;
+        let hero_bottom = bottom + (page_dims.1 - hero_dims.1) / 2.;
+        let mut page_text = include_str!("../data/page_hero.tex").to_string();
+        for (pattern, value) in &[
+            ("PHOTOTEX_HERO_LEFT", hero_left),
+            ("PHOTOTEX_HERO_BOTTOM", hero_bottom),
+            ("PHOTOTEX_HERO_WIDTH", hero_dims.0),
+            ("PHOTOTEX_HERO_HEIGHT", hero_dims.1),
+        ] {
+            replace(&mut page_text, pattern, &value.to_string()).unwrap();
         }
-        replace(&mut page_text, "PHOTOTEX_LEGEND", "%").unwrap();
-        write!(writer, "{}", page_text)?;
+        replace(
+            &mut page_text,
+            "PHOTOTEX_HERO_IMAGE",
+            &tex_path(&hero_path, &self.out_folder)?,
+        )
+        .unwrap();
+        let mut page_info = self.write_page(&page_text, PageKind::Hero)?;
+        page_info.extra_files.push(hero_path);
+        Ok(page_info)
+    }
 
-        Ok(PageInfo {
-            path: page_path,
-            kind: PageKind::OnePortrait,
-        })
+    /// Left page of a spread, `right_page` being the facing one. The photo
+    /// is split in two halves, each one against the inner margin of its
+    /// page, so that no part of it is lost in the binding.
+    pub(crate) fn write_spread(
+        self,
+        right_page: Page,
+        layout: &Layout,
+        im: &ImageInfo,
+    ) -> std::io::Result<Vec<PageInfo>> {
+        let (left_path, right_path) = split_in_halves(&im.path)
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        // the slot is centered on the fold: each half is as wide, in page
+        // widths, as the whole slot in spread widths
+        let slot = &layout.slots[0];
+        let (left, top, width, height) = slot.rect;
+        let left_slot = Slot {
+            rect: (2. * left, top, width, height),
+            ..slot.clone()
+        };
+        let right_slot = Slot {
+            rect: (0., top, width, height),
+            ..slot.clone()
+        };
+        let left_slots = slot_box(
+            &left_slot,
+            &tex_path(&left_path, &self.out_folder)?,
+            None,
+            Align::Right,
+        );
+        let right_slots = slot_box(
+            &right_slot,
+            &tex_path(&right_path, &right_page.out_folder)?,
+            im.caption.as_ref(),
+            Align::Left,
+        );
+        let mut left_page = self.write_slots(layout, &left_slots)?;
+        left_page.extra_files.push(left_path);
+        let mut right_page = right_page.write_slots(layout, &right_slots)?;
+        right_page.extra_files.push(right_path);
+        Ok(vec![left_page, right_page])
     }
 }
@@ -1,6 +1,9 @@
+use num_integer::Integer;
 use std::path::Path;
 use std::process::Command;
-use num_integer::Integer;
+
+use crate::book_structure::pdf_page_count;
+use crate::page_format::{PageBox, PageGeometry};
 
 pub fn generate_pdf(
     output_folder: &Path,
@@ -44,6 +47,56 @@ pub fn generate_pdf(
     Ok(pdf_file_name)
 }
 
+fn pdf_box((left, bottom, right, top): PageBox) -> lopdf::Object {
+    let points = |mm: f32| lopdf::Object::Real(f64::from(mm) * 72. / 25.4);
+    lopdf::Object::Array(vec![
+        points(left),
+        points(bottom),
+        points(right),
+        points(top),
+    ])
+}
+
+/// Set the TrimBox and BleedBox of every page of a generated PDF, telling the
+/// print shop where to cut the sheets
+pub fn set_page_boxes(
+    output_folder: &Path,
+    pdf_file_name: &str,
+    geometry: &PageGeometry,
+) -> std::io::Result<()> {
+    let pdf_path = output_folder.join(pdf_file_name);
+    let mut pdf = lopdf::Document::load(&pdf_path)?;
+    for page_id in pdf.get_pages().values() {
+        let page = pdf
+            .get_object_mut(*page_id)
+            .and_then(lopdf::Object::as_dict_mut)
+            .ok_or_else(|| {
+                log::error!(
+                    "invalid page object {:?} in {:?}",
+                    page_id,
+                    pdf_path
+                );
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "invalid page object",
+                )
+            })?;
+        page.set("BleedBox", pdf_box(geometry.bleed_box()));
+        page.set("TrimBox", pdf_box(geometry.trim_box()));
+    }
+    pdf.save(&pdf_path)?;
+    Ok(())
+}
+
+/// Number of pages of a generated PDF
+pub fn page_count(
+    output_folder: &Path,
+    pdf_file_name: &str,
+) -> std::io::Result<usize> {
+    let pdf = lopdf::Document::load(output_folder.join(pdf_file_name))?;
+    Ok(pdf.get_pages().len())
+}
+
 pub fn remove_second_third_covers(
     output_folder: &Path,
     full_pdf_file_name: &str,
@@ -59,6 +112,15 @@ pub fn remove_second_third_covers(
             "pdf is too short",
         ));
     }
+    let expected = pdf_page_count(nb_pages, false);
+    if page_numbers.len() != expected {
+        log::warn!(
+            "the pdf has {} pages instead of {}, some pages of photos may \
+             have overflowed",
+            page_numbers.len(),
+            expected,
+        );
+    }
     let to_delete = [page_numbers[1], page_numbers[page_numbers.len() - 2]];
     if nb_pages.is_even() {
         pdf.delete_pages(&to_delete);
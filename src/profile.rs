@@ -0,0 +1,282 @@
+//! Print shop profiles, gathering the requirements of a print shop for the
+//! uploaded PDF
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::book_structure::FillerKind;
+use crate::page_format;
+use crate::PageOrientation;
+
+/// Margins of the pages, in mm
+#[derive(Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Margins {
+    pub inner: f32,
+    pub outer: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+impl Default for Margins {
+    fn default() -> Self {
+        Margins {
+            inner: 20.,
+            outer: 10.,
+            top: 15.,
+            bottom: 15.,
+        }
+    }
+}
+
+impl Margins {
+    /// Width and height of the text area of pages of the given dimensions
+    pub fn text_dims(&self, page_dims: (f32, f32)) -> (f32, f32) {
+        (
+            page_dims.0 - self.inner - self.outer,
+            page_dims.1 - self.top - self.bottom,
+        )
+    }
+}
+
+/// Colours expected by the print shop
+#[derive(Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    #[default]
+    Rgb,
+    /// Images are converted to shades of gray
+    Grayscale,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrintProfile {
+    /// Name of a known page format, see `page_format`
+    page_format: String,
+    page_orientation: Option<PageOrientation>,
+    /// Custom page size, as `WxHmm`, replacing `page_format`
+    page_size: Option<String>,
+    /// Bleed around the pages, in mm
+    pub bleed: f32,
+    /// Draw crop marks around the bleed
+    pub crop_marks: bool,
+    pub margins: Margins,
+    /// The number of pages must be a multiple of this
+    pub page_multiple: usize,
+    pub min_pages: Option<usize>,
+    pub max_pages: Option<usize>,
+    /// Pages added at the end of the album to meet the page count rules
+    pub filler: FillerKind,
+    /// Remove the second and third covers from the uploaded PDF
+    pub strip_inner_covers: bool,
+    pub color: ColorMode,
+    /// Name of the uploaded PDF, where `{title}` and `{pages}` are replaced
+    /// by the title of the album and its number of pages
+    output_name: String,
+}
+
+impl Default for PrintProfile {
+    fn default() -> Self {
+        PrintProfile {
+            page_format: "A4".to_string(),
+            page_orientation: None,
+            page_size: None,
+            bleed: 0.,
+            crop_marks: false,
+            margins: Margins::default(),
+            page_multiple: 1,
+            min_pages: None,
+            max_pages: None,
+            filler: FillerKind::default(),
+            strip_inner_covers: false,
+            color: ColorMode::default(),
+            output_name: "{title}.pdf".to_string(),
+        }
+    }
+}
+
+/// Only keep characters that are safe in a file name
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+impl PrintProfile {
+    /// Page dimensions in mm and orientation of the pages
+    pub fn page_dims(&self) -> Result<((f32, f32), PageOrientation), String> {
+        if let Some(page_size) = &self.page_size {
+            let page_dims = page_format::parse_page_size(page_size)?;
+            return Ok((page_dims, page_format::page_orientation(page_dims)));
+        }
+        let page_orientation = self.page_orientation.unwrap_or(
+            match page_format::page_dims(
+                &self.page_format,
+                PageOrientation::Square,
+            ) {
+                Ok(_) => PageOrientation::Square,
+                Err(_) => PageOrientation::Portrait,
+            },
+        );
+        let page_dims =
+            page_format::page_dims(&self.page_format, page_orientation)?;
+        Ok((page_dims, page_orientation))
+    }
+
+    /// Check that the print shop accepts a PDF of `nb_pages` pages
+    pub fn check_page_count(&self, nb_pages: usize) -> Result<(), String> {
+        if !nb_pages.is_multiple_of(self.page_multiple.max(1)) {
+            return Err(format!(
+                "the album has {} pages, which is not a multiple of {}",
+                nb_pages, self.page_multiple,
+            ));
+        }
+        if let Some(min_pages) = self.min_pages.filter(|&m| nb_pages < m) {
+            return Err(format!(
+                "the album has {} pages, the minimum is {}",
+                nb_pages, min_pages,
+            ));
+        }
+        if let Some(max_pages) = self.max_pages.filter(|&m| nb_pages > m) {
+            return Err(format!(
+                "the album has {} pages, the maximum is {}",
+                nb_pages, max_pages,
+            ));
+        }
+        Ok(())
+    }
+
+    /// File name of the uploaded PDF
+    pub fn output_file_name(&self, title: &str, nb_pages: usize) -> String {
+        let title = if title.trim().is_empty() {
+            "photobook"
+        } else {
+            title.trim()
+        };
+        let name = self
+            .output_name
+            .replace("{title}", title)
+            .replace("{pages}", &nb_pages.to_string());
+        let name = sanitize_file_name(&name);
+        if name.ends_with(".pdf") {
+            name
+        } else {
+            format!("{}.pdf", name)
+        }
+    }
+}
+
+fn parse_profiles(
+    profiles: &str,
+) -> Result<BTreeMap<String, PrintProfile>, serde_json::Error> {
+    serde_json::from_str(profiles)
+}
+
+/// Profiles shipped with phototex
+pub fn builtin_profiles() -> BTreeMap<String, PrintProfile> {
+    parse_profiles(include_str!("../data/profiles.json"))
+        .expect("invalid builtin profiles")
+}
+
+/// Find a profile by name, in the user profiles file if any, and then in the
+/// builtin profiles
+pub fn find_profile(
+    name: &str,
+    user_profiles: Option<&Path>,
+) -> Result<PrintProfile, String> {
+    if let Some(path) = user_profiles {
+        let profiles = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {:?}: {}", path, e))?;
+        let mut profiles = parse_profiles(&profiles)
+            .map_err(|e| format!("invalid profiles file {:?}: {}", path, e))?;
+        if let Some(profile) = profiles.remove(name) {
+            page_format::check_bleed(profile.bleed).map_err(|e| {
+                format!("invalid profile {} in {:?}: {}", name, path, e)
+            })?;
+            return Ok(profile);
+        }
+    }
+    let mut profiles = builtin_profiles();
+    profiles.remove(name).ok_or_else(|| {
+        format!(
+            "unknown print profile {}, builtin profiles are: {}",
+            name,
+            profiles.keys().cloned().collect::<Vec<_>>().join(", "),
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{builtin_profiles, find_profile, page_format, parse_profiles};
+    use crate::book_structure::FillerKind;
+    use crate::PageOrientation;
+
+    #[test]
+    fn builtin_profiles_are_valid() {
+        for (name, profile) in builtin_profiles() {
+            assert!(profile.page_dims().is_ok(), "{}", name);
+            assert!(
+                page_format::check_bleed(profile.bleed).is_ok(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn user_profile() {
+        let profiles = parse_profiles(
+            r#"{"shop": {
+                "page_size": "200x250mm",
+                "page_multiple": 4,
+                "min_pages": 24,
+                "max_pages": 100,
+                "filler": "mosaic",
+                "color": "grayscale",
+                "output_name": "{title} ({pages} pages)"
+            }}"#,
+        )
+        .unwrap();
+        let profile = &profiles["shop"];
+        assert_eq!(
+            profile.page_dims(),
+            Ok(((200., 250.), PageOrientation::Portrait))
+        );
+        assert!(profile.check_page_count(22).is_err());
+        assert!(profile.check_page_count(26).is_err());
+        assert!(profile.check_page_count(28).is_ok());
+        assert!(profile.check_page_count(104).is_err());
+        assert_eq!(
+            profile.output_file_name("Été 2016", 28),
+            "Été_2016__28_pages_.pdf"
+        );
+        assert_eq!(profile.margins, super::Margins::default());
+        assert_eq!(profile.filler, FillerKind::Mosaic);
+    }
+
+    #[test]
+    fn misspelled_keys() {
+        assert!(parse_profiles(r#"{"shop": {"blead": 3}}"#).is_err());
+        assert!(
+            parse_profiles(r#"{"shop": {"margins": {"iner": 20}}}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn negative_bleed() {
+        let path = std::env::temp_dir().join("phototex_negative_bleed.json");
+        std::fs::write(&path, r#"{"shop": {"bleed": -3}}"#).unwrap();
+        let error = find_profile("shop", Some(&path)).unwrap_err();
+        assert!(error.contains("invalid bleed: -3"), "{}", error);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
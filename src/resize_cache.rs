@@ -0,0 +1,181 @@
+//! Manifest of the resized copies of the images, used to only resize again
+//! the images whose source or target has changed
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+const CACHE_FILE_NAME: &str = "resize_cache.json";
+
+/// Everything a resized copy depends on
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub(crate) struct CacheEntry {
+    source_hash: String,
+    dims: (u32, u32),
+    orientation: String,
+    encoder: String,
+}
+
+impl CacheEntry {
+    pub(crate) fn new(
+        source: &Path,
+        dims: (u32, u32),
+        orientation: &str,
+        encoder: &str,
+    ) -> std::io::Result<CacheEntry> {
+        Ok(CacheEntry {
+            source_hash: content_hash(source)?,
+            dims,
+            orientation: orientation.to_string(),
+            encoder: encoder.to_string(),
+        })
+    }
+}
+
+/// 64 bits FNV-1a hash of the contents of a file, along with its length
+fn content_hash(path: &Path) -> std::io::Result<String> {
+    let mut f = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut buf = [0; 64 * 1024];
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut len = 0;
+    loop {
+        let nb_read = f.read(&mut buf)?;
+        if nb_read == 0 {
+            break;
+        }
+        len += nb_read;
+        hash = buf[..nb_read].iter().fold(hash, |hash, &b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+    }
+    Ok(format!("{:016x}-{}", hash, len))
+}
+
+/// Cache entries of the resized images, keyed by their path relative to the
+/// images folder
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub(crate) struct ResizeCache {
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+impl ResizeCache {
+    /// Load the cache of an images folder. A missing or unreadable cache
+    /// is considered empty, which means every image will be resized.
+    pub(crate) fn load(images_path: &Path) -> ResizeCache {
+        let cache_path = images_path.join(CACHE_FILE_NAME);
+        let cache = match std::fs::read_to_string(&cache_path) {
+            Ok(cache) => cache,
+            Err(_) => return ResizeCache::default(),
+        };
+        serde_json::from_str(&cache).unwrap_or_else(|e| {
+            log::warn!("ignoring invalid resize cache {:?}: {}", cache_path, e);
+            ResizeCache::default()
+        })
+    }
+
+    pub(crate) fn save(&self, images_path: &Path) -> std::io::Result<()> {
+        let cache = serde_json::to_string_pretty(self)?;
+        let tmp_path = images_path.join(format!("{}.tmp", CACHE_FILE_NAME));
+        std::fs::write(&tmp_path, cache)?;
+        std::fs::rename(tmp_path, images_path.join(CACHE_FILE_NAME))
+    }
+
+    /// Whether the resized copy at `rel_path` exists and was created from
+    /// the same inputs
+    pub(crate) fn is_fresh(
+        &self,
+        images_path: &Path,
+        rel_path: &str,
+        entry: &CacheEntry,
+    ) -> bool {
+        self.entries.get(rel_path) == Some(entry)
+            && images_path.join(rel_path).is_file()
+    }
+
+    pub(crate) fn insert(&mut self, rel_path: String, entry: CacheEntry) {
+        self.entries.insert(rel_path, entry);
+    }
+
+    /// Only keep the entries of the paths for which `keep` is true
+    pub(crate) fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        self.entries.retain(|rel_path, _| keep(rel_path));
+    }
+}
+
+/// Remove the files of the images folder for which `keep` is false, such as
+/// the copies of deleted sources or of renumbered sections, and the folders
+/// left empty.
+pub(crate) fn remove_stale_images(
+    images_path: &Path,
+    keep: &dyn Fn(&str) -> bool,
+) -> std::io::Result<()> {
+    remove_stale_in(images_path, "", keep)
+}
+
+fn remove_stale_in(
+    folder: &Path,
+    rel_folder: &str,
+    keep: &dyn Fn(&str) -> bool,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(folder)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => continue,
+        };
+        let rel_path = if rel_folder.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", rel_folder, name)
+        };
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            remove_stale_in(&path, &rel_path, keep)?;
+            if std::fs::read_dir(&path)?.next().is_none() {
+                std::fs::remove_dir(&path)?;
+            }
+        } else if rel_path != CACHE_FILE_NAME && !keep(&rel_path) {
+            log::info!("removing stale image {:?}", path);
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{remove_stale_images, CacheEntry, ResizeCache};
+
+    #[test]
+    fn cache_freshness_and_cleanup() {
+        let images_path = std::env::temp_dir().join("phototex_resize_cache");
+        let _ = std::fs::remove_dir_all(&images_path);
+        for rel_path in &["section_00/a.jpg", "section_01/b.jpg"] {
+            let path = images_path.join(rel_path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, rel_path).unwrap();
+        }
+        let source = images_path.join("section_00/a.jpg");
+        let entry =
+            CacheEntry::new(&source, (400, 300), "Keep", "jpeg").unwrap();
+        let mut cache = ResizeCache::default();
+        cache.insert("section_00/a.jpg".to_string(), entry.clone());
+        cache.save(&images_path).unwrap();
+
+        let cache = ResizeCache::load(&images_path);
+        assert!(cache.is_fresh(&images_path, "section_00/a.jpg", &entry));
+        let resized =
+            CacheEntry::new(&source, (800, 600), "Keep", "jpeg").unwrap();
+        assert!(!cache.is_fresh(&images_path, "section_00/a.jpg", &resized));
+        assert!(!cache.is_fresh(&images_path, "section_01/b.jpg", &entry));
+
+        remove_stale_images(&images_path, &|p| p == "section_00/a.jpg")
+            .unwrap();
+        assert!(images_path.join("section_00/a.jpg").is_file());
+        assert!(!images_path.join("section_01").exists());
+        assert!(images_path.join("resize_cache.json").is_file());
+        std::fs::remove_dir_all(&images_path).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+//! Automatic splitting of a flat images folder into sections
+use chrono::Duration;
+
+use crate::latex::TexText;
+use crate::locale::Language;
+use crate::specs::{parse_time_offset, FolderSpec, RootSpec};
+use crate::{SourceFolderInfo, SourceImageInfo};
+
+/// Rule used to split a flat folder of images into sections
+#[derive(Copy, Clone, Debug)]
+pub enum AutoSections {
+    /// One section per calendar day
+    Day,
+    /// A new section starts when two consecutive photos are further apart
+    /// than the given duration
+    Gap(Duration),
+}
+
+impl std::str::FromStr for AutoSections {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "day" {
+            return Ok(AutoSections::Day);
+        }
+        let gap = parse_time_offset(s).map_err(|_| {
+            format!(
+                "unsupported auto sections value: {}, expected 'day' or a \
+                 gap as HH:MM",
+                s
+            )
+        })?;
+        if gap <= Duration::zero() {
+            return Err(format!("auto sections gap must be positive: {}", s));
+        }
+        Ok(AutoSections::Gap(gap))
+    }
+}
+
+/// Split images into sections. A section spec can be given in the root spec,
+/// keyed by the date of the first image of the section (`YYYY-MM-DD`).
+/// Sections without a title are named after their date range.
+pub(crate) fn split_sections(
+    mut image_infos: Vec<SourceImageInfo>,
+    auto_sections: AutoSections,
+    root_spec: &RootSpec,
+    language: Language,
+) -> Vec<SourceFolderInfo> {
+    image_infos.sort_by_key(|im| im.date);
+    let mut groups: Vec<Vec<SourceImageInfo>> = Vec::new();
+    for im in image_infos {
+        let new_section = match groups.last().and_then(|g| g.last()) {
+            None => true,
+            Some(prev) => match auto_sections {
+                AutoSections::Day => prev.date.date() != im.date.date(),
+                AutoSections::Gap(gap) => im.date - prev.date > gap,
+            },
+        };
+        if new_section {
+            groups.push(Vec::new());
+        }
+        groups.last_mut().unwrap().push(im);
+    }
+    groups
+        .into_iter()
+        .map(|image_infos| {
+            let first = image_infos[0].date.date();
+            let last = image_infos[image_infos.len() - 1].date.date();
+            let key = first.format("%Y-%m-%d").to_string();
+            let mut folder_spec = root_spec
+                .section_spec(&key)
+                .cloned()
+                .unwrap_or_else(FolderSpec::empty);
+            folder_spec.set_default_title(TexText::plain(
+                language.format_date_range(first, last),
+            ));
+            log::info!(
+                "Section {:?} with {} images",
+                folder_spec.section_title().map(TexText::to_latex),
+                image_infos.len(),
+            );
+            SourceFolderInfo {
+                folder_spec,
+                image_infos,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::latex::TexText;
+    use crate::locale::Language;
+    use crate::{LayoutReq, Orientation, SourceImageInfo};
+
+    fn image(name: &str, date: &str) -> SourceImageInfo {
+        SourceImageInfo {
+            path: PathBuf::from(name),
+            dimensions: (400, 300),
+            orientation: Orientation::Keep,
+            date: date.parse().unwrap(),
+            camera: None,
+            caption: None,
+            user_req: LayoutReq::Nothing,
+        }
+    }
+
+    #[test]
+    fn split_sections() {
+        use super::{split_sections, AutoSections};
+        let images = vec![
+            image("c.jpg", "2016-08-10T09:00:00"),
+            image("a.jpg", "2016-08-09T10:00:00"),
+            image("b.jpg", "2016-08-09T23:30:00"),
+            image("d.jpg", "2016-08-12T18:00:00"),
+        ];
+        let root_spec = serde_json::from_str(
+            r#"{"sections": {"2016-08-12": {"title": "Back home"}}}"#,
+        )
+        .unwrap();
+        let sections = split_sections(
+            images,
+            AutoSections::Day,
+            &root_spec,
+            Language::English,
+        );
+        let titles: Vec<_> = sections
+            .iter()
+            .map(|s| s.folder_spec.section_title().unwrap().to_latex())
+            .collect();
+        assert_eq!(titles, ["9 August 2016", "10 August 2016", "Back home"]);
+        assert_eq!(sections[0].image_infos.len(), 2);
+
+        let images = vec![
+            image("a.jpg", "2016-08-09T10:00:00"),
+            image("b.jpg", "2016-08-09T23:30:00"),
+            image("c.jpg", "2016-08-10T09:00:00"),
+            image("d.jpg", "2016-08-12T18:00:00"),
+        ];
+        let gap = "14:00".parse().unwrap();
+        let sections =
+            split_sections(images, gap, &root_spec, Language::English);
+        let sizes: Vec<_> =
+            sections.iter().map(|s| s.image_infos.len()).collect();
+        assert_eq!(sizes, [3, 1]);
+        assert_eq!(
+            sections[0].folder_spec.section_title(),
+            Some(&TexText::plain("9 – 10 August 2016")),
+        );
+    }
+}
@@ -1,12 +1,439 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::path::Path;
 
-use serde::Deserialize;
+use chrono::Duration;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
 
+use crate::latex::TexText;
+
+/// How images are ordered inside a section
+#[derive(Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Chronological order, using the EXIF creation date when available
+    Date,
+    /// Alphabetical order of the file names
+    Name,
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "date" => Ok(SortOrder::Date),
+            "name" => Ok(SortOrder::Name),
+            _ => Err(format!("unsupported sort order: {}", s)),
+        }
+    }
+}
+
+/// How a hero photo fills a page of another orientation, such as a
+/// landscape on a portrait page
+#[derive(Deserialize, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HeroFit {
+    /// The photo fills the width of the page, cropped only where it
+    /// overflows its height
+    #[default]
+    Crop,
+    /// The photo is turned a quarter, to be seen by turning the book
+    Rotate,
+}
+
+impl std::str::FromStr for HeroFit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "crop" => Ok(HeroFit::Crop),
+            "rotate" => Ok(HeroFit::Rotate),
+            _ => Err(format!("unsupported hero fit: {}", s)),
+        }
+    }
+}
+
+/// Parse a clock offset of the form `[+-]HH:MM[:SS]`
+pub fn parse_time_offset(s: &str) -> Result<Duration, String> {
+    let err = || format!("invalid time offset: {:?}, expected +HH:MM[:SS]", s);
+    let (sign, rest) = match s.chars().next() {
+        Some('+') => (1, &s[1..]),
+        Some('-') => (-1, &s[1..]),
+        _ => (1, s),
+    };
+    // only the whole offset has a sign
+    let fields = rest
+        .split(':')
+        .map(|f| {
+            if f.is_empty() || !f.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(err());
+            }
+            f.parse::<i64>().map_err(|_| err())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let (hours, minutes, seconds) = match fields[..] {
+        [h, m] => (h, m, 0),
+        [h, m, s] => (h, m, s),
+        _ => return Err(err()),
+    };
+    if minutes >= 60 || seconds >= 60 {
+        return Err(err());
+    }
+    Ok(Duration::seconds(
+        sign * (hours * 3600 + minutes * 60 + seconds),
+    ))
+}
+
+fn deserialize_time_offset<'de, D>(
+    deserializer: D,
+) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_time_offset(&s).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_pattern<'de, D>(
+    deserializer: D,
+) -> Result<Option<glob::Pattern>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    glob::Pattern::new(&s)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn deserialize_patterns<'de, D>(
+    deserializer: D,
+) -> Result<Vec<glob::Pattern>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| glob::Pattern::new(s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Rule leaving an image out of the album: the first `exclude` pattern
+/// matching one of its names, or the `include` patterns when there are some
+/// and none matches
+fn filter_rule(
+    include: &[glob::Pattern],
+    exclude: &[glob::Pattern],
+    names: &[&str],
+) -> Option<String> {
+    let matches = |p: &glob::Pattern| names.iter().any(|n| p.matches(n));
+    if let Some(pattern) = exclude.iter().find(|p| matches(p)) {
+        return Some(format!("exclude {:?}", pattern.as_str()));
+    }
+    if !include.is_empty() && !include.iter().any(matches) {
+        let patterns: Vec<_> = include.iter().map(|p| p.as_str()).collect();
+        return Some(format!("include {:?}", patterns));
+    }
+    None
+}
+
+/// Filters on the rating and tags that photo management software writes in
+/// the XMP metadata of the images. Unset filters keep every image.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetadataFilter {
+    /// Minimum number of stars, unrated images counting as 0 and rejected
+    /// ones as -1
+    pub min_rating: Option<i32>,
+    /// Tags of which the images must have at least one
+    pub tags: Option<Vec<String>>,
+    /// Tags of which the images must have none
+    pub exclude_tags: Option<Vec<String>>,
+}
+
+impl MetadataFilter {
+    pub fn is_empty(&self) -> bool {
+        self.min_rating.is_none()
+            && self.tags.as_ref().is_none_or(Vec::is_empty)
+            && self.exclude_tags.as_ref().is_none_or(Vec::is_empty)
+    }
+
+    /// The filters of `self` that `other` does not set
+    pub fn not_set_in(&self, other: &MetadataFilter) -> MetadataFilter {
+        MetadataFilter {
+            min_rating: self.min_rating.filter(|_| other.min_rating.is_none()),
+            tags: self.tags.clone().filter(|_| other.tags.is_none()),
+            exclude_tags: self
+                .exclude_tags
+                .clone()
+                .filter(|_| other.exclude_tags.is_none()),
+        }
+    }
+
+    /// Rule leaving out an image of this rating and these tags, if any. Tags
+    /// are compared without case.
+    pub fn rule(&self, rating: i32, tags: &[String]) -> Option<String> {
+        let has_tag = |tag: &String| {
+            tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase())
+        };
+        if let Some(min_rating) = self.min_rating.filter(|&r| rating < r) {
+            return Some(format!("min_rating {}", min_rating));
+        }
+        if let Some(tag) =
+            self.exclude_tags.iter().flatten().find(|t| has_tag(t))
+        {
+            return Some(format!("exclude_tags {:?}", tag));
+        }
+        match &self.tags {
+            Some(wanted)
+                if !wanted.is_empty() && !wanted.iter().any(has_tag) =>
+            {
+                Some(format!("tags {:?}", wanted))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Read a spec file, if there is one. Errors give the position in the file
+/// of the invalid JSON or of the unknown key.
+fn load_spec<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+    serde_json::from_reader(std::io::BufReader::new(file))
+        .map(Some)
+        .map_err(|e| {
+            let message = e.to_string();
+            let position =
+                format!(" at line {} column {}", e.line(), e.column());
+            format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                e.line(),
+                e.column(),
+                message.strip_suffix(&position).unwrap_or(&message),
+            )
+        })
+}
+
+/// Clock correction applied to the images of a camera, or to the images
+/// whose file name matches a glob pattern. When both `camera` and `files`
+/// are given, both must match.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "TimeOffsetRepr")]
+pub struct TimeOffset {
+    /// Camera name, as listed in the detected cameras report
+    camera: Option<String>,
+    files: Option<glob::Pattern>,
+    offset: Duration,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimeOffsetRepr {
+    camera: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_pattern")]
+    files: Option<glob::Pattern>,
+    #[serde(deserialize_with = "deserialize_time_offset")]
+    offset: Duration,
+}
+
+impl TryFrom<TimeOffsetRepr> for TimeOffset {
+    type Error = String;
+
+    fn try_from(repr: TimeOffsetRepr) -> Result<Self, Self::Error> {
+        if repr.camera.is_none() && repr.files.is_none() {
+            return Err("a time offset needs a camera or files".to_string());
+        }
+        Ok(TimeOffset {
+            camera: repr.camera,
+            files: repr.files,
+            offset: repr.offset,
+        })
+    }
+}
+
+impl TimeOffset {
+    fn matches(&self, camera: Option<&str>, file_name: &str) -> bool {
+        let camera_ok = self
+            .camera
+            .as_ref()
+            .is_none_or(|c| Some(c.as_str()) == camera);
+        let files_ok = self.files.as_ref().is_none_or(|p| p.matches(file_name));
+        camera_ok && files_ok
+    }
+}
+
+fn find_time_offset(
+    offsets: &[TimeOffset],
+    camera: Option<&str>,
+    file_name: &str,
+) -> Option<Duration> {
+    offsets
+        .iter()
+        .find(|o| o.matches(camera, file_name))
+        .map(|o| o.offset)
+}
+
+/// Specification for the whole album, read from the `specs.json` file at
+/// the root of the images folder.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RootSpec {
+    #[serde(default)]
+    time_offsets: Vec<TimeOffset>,
+    /// Specs of automatically generated sections, keyed by the date of
+    /// their first image (`YYYY-MM-DD`)
+    #[serde(default)]
+    sections: BTreeMap<String, FolderSpec>,
+    /// Captions of images, keyed by file name
+    #[serde(default)]
+    captions: BTreeMap<String, TexText>,
+    /// Patterns of the images kept in the album, if not all of them
+    #[serde(default, deserialize_with = "deserialize_patterns")]
+    include: Vec<glob::Pattern>,
+    /// Patterns of the images left out of the album
+    #[serde(default, deserialize_with = "deserialize_patterns")]
+    exclude: Vec<glob::Pattern>,
+}
+
+impl RootSpec {
+    /// Rule leaving an image out of the album, if any. The patterns are
+    /// matched against its file name and its path from the images folder.
+    pub fn filter_rule(
+        &self,
+        file_name: &str,
+        rel_path: &str,
+    ) -> Option<String> {
+        filter_rule(&self.include, &self.exclude, &[file_name, rel_path])
+    }
+
+    pub fn section_spec(&self, first_date: &str) -> Option<&FolderSpec> {
+        self.sections.get(first_date)
+    }
+
+    pub fn caption(&self, file_name: &str) -> Option<&TexText> {
+        self.captions.get(file_name)
+    }
+
+    pub fn time_offset(
+        &self,
+        camera: Option<&str>,
+        file_name: &str,
+    ) -> Option<Duration> {
+        find_time_offset(&self.time_offsets, camera, file_name)
+    }
+
+    /// Specs of the automatically generated sections, with their keys
+    pub fn section_specs(&self) -> impl Iterator<Item = (&str, &FolderSpec)> {
+        self.sections.iter().map(|(key, spec)| (key.as_str(), spec))
+    }
+
+    /// Names of the images given a caption
+    pub fn captioned_images(&self) -> impl Iterator<Item = &str> {
+        self.captions.keys().map(String::as_str)
+    }
+
+    /// Read the root spec, the spec being empty when there is no file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let spec = load_spec(path)?.unwrap_or_default();
+        log::info!("Loaded root spec: {:?}:\n\t{:?}", path, spec);
+        Ok(spec)
+    }
+}
+
+/// Images pinned to one page, optionally with the name of its layout, whose
+/// slots they fill in their order. In `specs.json`, either the list of the
+/// images or an object with `images` and an optional `layout`.
 #[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "PageSpecRepr")]
+pub struct PageSpec {
+    images: Vec<String>,
+    layout: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PageSpecObject {
+    images: Vec<String>,
+    layout: Option<String>,
+}
+
+/// The object form is parsed apart, so that its errors are not hidden
+/// behind the ones of the untagged enum
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PageSpecRepr {
+    Images(Vec<String>),
+    Object(serde_json::Value),
+}
+
+impl TryFrom<PageSpecRepr> for PageSpec {
+    type Error = String;
+
+    fn try_from(repr: PageSpecRepr) -> Result<Self, Self::Error> {
+        match repr {
+            PageSpecRepr::Images(images) => Ok(PageSpec {
+                images,
+                layout: None,
+            }),
+            PageSpecRepr::Object(value) => {
+                let page: PageSpecObject = serde_json::from_value(value)
+                    .map_err(|e| format!("invalid page: {}", e))?;
+                Ok(PageSpec {
+                    images: page.images,
+                    layout: page.layout,
+                })
+            }
+        }
+    }
+}
+
+impl PageSpec {
+    pub fn images(&self) -> &[String] {
+        &self.images
+    }
+
+    pub fn layout(&self) -> Option<&str> {
+        self.layout.as_deref()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct FolderSpec {
-    title: Option<String>,
+    title: Option<TexText>,
     #[serde(default)]
     one_portraits: Vec<String>,
+    /// Images shown alone on a full-bleed page, without margins nor
+    /// headings
+    #[serde(default)]
+    heroes: Vec<String>,
+    hero_fit: Option<HeroFit>,
+    /// Pages whose images are chosen, the other images being laid out
+    /// around them
+    #[serde(default)]
+    pages: Vec<PageSpec>,
+    sort_by: Option<SortOrder>,
+    #[serde(default)]
+    time_offsets: Vec<TimeOffset>,
+    /// Captions of images, keyed by file name
+    #[serde(default)]
+    captions: BTreeMap<String, TexText>,
+    /// Patterns of the images kept in the album, if not all of them
+    #[serde(default, deserialize_with = "deserialize_patterns")]
+    include: Vec<glob::Pattern>,
+    /// Patterns of the images left out of the album
+    #[serde(default, deserialize_with = "deserialize_patterns")]
+    exclude: Vec<glob::Pattern>,
+    /// Filters on the XMP metadata, replacing the ones of the command line
+    min_rating: Option<i32>,
+    tags: Option<Vec<String>>,
+    exclude_tags: Option<Vec<String>>,
 }
 
 impl FolderSpec {
@@ -14,6 +441,31 @@ impl FolderSpec {
         FolderSpec {
             title: None,
             one_portraits: Vec::new(),
+            heroes: Vec::new(),
+            hero_fit: None,
+            pages: Vec::new(),
+            sort_by: None,
+            time_offsets: Vec::new(),
+            captions: BTreeMap::new(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            min_rating: None,
+            tags: None,
+            exclude_tags: None,
+        }
+    }
+
+    /// Rule leaving an image out of the album, if any
+    pub fn filter_rule(&self, file_name: &str) -> Option<String> {
+        filter_rule(&self.include, &self.exclude, &[file_name])
+    }
+
+    /// Filters on the XMP metadata set by this folder
+    pub fn metadata_filter(&self) -> MetadataFilter {
+        MetadataFilter {
+            min_rating: self.min_rating,
+            tags: self.tags.clone(),
+            exclude_tags: self.exclude_tags.clone(),
         }
     }
 
@@ -21,19 +473,242 @@ impl FolderSpec {
         &self.one_portraits
     }
 
-    pub fn section_title(&self) -> Option<&str> {
-        self.title.as_ref().map(|t| t.as_str())
+    pub fn heroes(&self) -> &[String] {
+        &self.heroes
+    }
+
+    pub fn pages(&self) -> &[PageSpec] {
+        &self.pages
+    }
+
+    /// How the heroes of this folder fill the pages, if it overrides the
+    /// global setting
+    pub fn hero_fit(&self) -> Option<HeroFit> {
+        self.hero_fit
+    }
+
+    pub fn section_title(&self) -> Option<&TexText> {
+        self.title.as_ref()
+    }
+
+    pub fn caption(&self, file_name: &str) -> Option<&TexText> {
+        self.captions.get(file_name)
+    }
+
+    /// Set the section title, unless the spec already defines one
+    pub fn set_default_title(&mut self, title: TexText) {
+        if self.title.is_none() {
+            self.title = Some(title);
+        }
+    }
+
+    /// Sort order requested for this folder, if it overrides the global one
+    pub fn sort_by(&self) -> Option<SortOrder> {
+        self.sort_by
+    }
+
+    /// Clock correction for an image of this folder, if one matches
+    pub fn time_offset(
+        &self,
+        camera: Option<&str>,
+        file_name: &str,
+    ) -> Option<Duration> {
+        find_time_offset(&self.time_offsets, camera, file_name)
+    }
+
+    /// Images named in the spec, along with the key naming them
+    pub fn image_references(&self) -> Vec<(&'static str, &str)> {
+        fn listed<'a>(
+            key: &'static str,
+            names: &'a [String],
+        ) -> impl Iterator<Item = (&'static str, &'a str)> {
+            names.iter().map(move |name| (key, name.as_str()))
+        }
+        listed("one_portraits", &self.one_portraits)
+            .chain(listed("heroes", &self.heroes))
+            .chain(self.pages.iter().flat_map(|p| listed("pages", p.images())))
+            .chain(self.captions.keys().map(|name| ("captions", name.as_str())))
+            .collect()
+    }
+
+    /// Keys of the spec applying to the images before they are split into
+    /// generated sections, which the spec of such a section cannot set
+    pub fn image_selection_keys(&self) -> Vec<&'static str> {
+        let keys = [
+            ("time_offsets", !self.time_offsets.is_empty()),
+            ("include", !self.include.is_empty()),
+            ("exclude", !self.exclude.is_empty()),
+            ("min_rating", self.min_rating.is_some()),
+            ("tags", self.tags.is_some()),
+            ("exclude_tags", self.exclude_tags.is_some()),
+        ];
+        keys.iter()
+            .filter(|(_, set)| *set)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    /// Read a folder spec, the spec being empty when there is no file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let spec = load_spec(path)?.unwrap_or_else(FolderSpec::empty);
+        log::info!("Loaded folder spec: {:?}:\n\t{:?}", path, spec);
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Duration;
+
+    #[test]
+    fn parse_time_offset() {
+        use super::parse_time_offset;
+        assert_eq!(parse_time_offset("+02:00"), Ok(Duration::hours(2)));
+        assert_eq!(parse_time_offset("-1:30"), Ok(-Duration::minutes(90)),);
+        assert_eq!(parse_time_offset("00:00:42"), Ok(Duration::seconds(42)),);
+        assert!(parse_time_offset("2h").is_err());
+        assert!(parse_time_offset("+02:75").is_err());
+        assert!(parse_time_offset("+01:-30").is_err());
+        assert!(parse_time_offset("-+01:30").is_err());
+        assert!(parse_time_offset("01::30").is_err());
+        let offsets = serde_json::from_str::<Vec<super::TimeOffset>>(
+            r#"[{"offset": "+01:00"}]"#,
+        );
+        assert!(offsets.unwrap_err().to_string().contains("needs a camera"));
+    }
+
+    #[test]
+    fn page_specs() {
+        let spec: super::FolderSpec = serde_json::from_str(
+            r#"{"pages": [
+                ["a.jpg", "b.jpg"],
+                {"images": ["c.jpg"], "layout": "one"}
+            ]}"#,
+        )
+        .unwrap();
+        let pages = spec.pages();
+        assert_eq!(pages[0].images(), ["a.jpg", "b.jpg"]);
+        assert_eq!(pages[0].layout(), None);
+        assert_eq!(pages[1].images(), ["c.jpg"]);
+        assert_eq!(pages[1].layout(), Some("one"));
+        let spec: super::FolderSpec =
+            serde_json::from_str(r#"{"pages": [{"images": ["d.jpg"]}]}"#)
+                .unwrap();
+        assert_eq!(spec.pages()[0].layout(), None);
+        let error = serde_json::from_str::<super::FolderSpec>(
+            r#"{"pages": [{"images": ["d.jpg"], "layuot": "one"}]}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("unknown field `layuot`"));
+    }
+
+    #[test]
+    fn filter_rules() {
+        let spec: super::RootSpec = serde_json::from_str(
+            r#"{"include": ["IMG_*", "DSC_*"], "exclude": ["*_raw.jpg"]}"#,
+        )
+        .unwrap();
+        assert_eq!(spec.filter_rule("IMG_1.jpg", "a/IMG_1.jpg"), None);
+        assert_eq!(
+            spec.filter_rule("IMG_1_raw.jpg", "a/IMG_1_raw.jpg"),
+            Some(r#"exclude "*_raw.jpg""#.to_string()),
+        );
+        assert_eq!(
+            spec.filter_rule("PXL_1.jpg", "a/PXL_1.jpg"),
+            Some(r#"include ["IMG_*", "DSC_*"]"#.to_string()),
+        );
+        let spec: super::RootSpec =
+            serde_json::from_str(r#"{"exclude": ["b/*"]}"#).unwrap();
+        assert!(spec.filter_rule("IMG_1.jpg", "a/IMG_1.jpg").is_none());
+        assert!(spec.filter_rule("IMG_1.jpg", "b/IMG_1.jpg").is_some());
+    }
+
+    #[test]
+    fn metadata_filters() {
+        let tags = |tags: &[&str]| -> Vec<String> {
+            tags.iter().map(|t| t.to_string()).collect()
+        };
+        let global = super::MetadataFilter {
+            min_rating: Some(3),
+            tags: None,
+            exclude_tags: Some(tags(&["private"])),
+        };
+        assert_eq!(global.rule(4, &tags(&["Family"])), None);
+        assert_eq!(global.rule(2, &[]), Some("min_rating 3".to_string()));
+        assert_eq!(
+            global.rule(5, &tags(&["Private"])),
+            Some(r#"exclude_tags "private""#.to_string()),
+        );
+        let spec: super::FolderSpec =
+            serde_json::from_str(r#"{"min_rating": 1, "tags": ["family"]}"#)
+                .unwrap();
+        let folder = spec.metadata_filter();
+        assert_eq!(folder.rule(2, &tags(&["family"])), None);
+        assert_eq!(
+            folder.rule(2, &tags(&["friends"])),
+            Some(r#"tags ["family"]"#.to_string()),
+        );
+        let remaining = global.not_set_in(&folder);
+        assert_eq!(remaining.min_rating, None);
+        assert_eq!(remaining.exclude_tags, Some(tags(&["private"])));
+        assert!(!remaining.is_empty());
+        assert!(super::MetadataFilter::default().is_empty());
+    }
+
+    #[test]
+    fn image_selection_keys() {
+        let spec: super::FolderSpec = serde_json::from_str(
+            r#"{"title": "Rathlin Island", "exclude": ["*_raw.jpg"],
+                "tags": [], "time_offsets": [
+                    {"camera": "Canon EOS 550D", "offset": "+02:00"}
+                ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            spec.image_selection_keys(),
+            ["time_offsets", "exclude", "tags"],
+        );
+        assert!(super::FolderSpec::empty().image_selection_keys().is_empty());
+    }
+
+    #[test]
+    fn strict_loading() {
+        let dir = std::env::temp_dir().join("phototex_strict_specs");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("specs.json");
+        let load = |text: &str| {
+            std::fs::write(&path, text).unwrap();
+            super::FolderSpec::load(&path)
+        };
+        assert!(load(r#"{"title": "Rathlin Island"}"#).is_ok());
+        let error = load("{\n  \"title\": \"Rathlin Island\",\n}").unwrap_err();
+        assert_eq!(error, format!("{}:3:1: trailing comma", path.display()));
+        let error = load(r#"{"one_portrait": ["a.jpg"]}"#).unwrap_err();
+        assert!(error.contains(":1:16: unknown field `one_portrait`"));
+        assert!(
+            load(r#"{"pages": [{"images": [], "layuot": "one"}]}"#).is_err()
+        );
+        assert!(load(r#"{"title": {"latex": "a", "plain": "b"}}"#).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(super::RootSpec::load(&path).is_ok());
     }
 
-    pub fn load_or_empty(path: &Path) -> Self {
-        std::fs::File::open(path)
-            .map(std::io::BufReader::new)
-            .map(serde_json::from_reader)
-            .map(|spec| {
-                log::info!("Loaded folder spec: {:?}:\n\t{:?}", path, spec);
-                spec
-            })
-            .unwrap_or(Ok(FolderSpec::empty()))
-            .unwrap_or(FolderSpec::empty())
+    #[test]
+    fn time_offset_matching() {
+        let offsets: Vec<super::TimeOffset> = serde_json::from_str(
+            r#"[
+                {"camera": "Canon EOS 550D", "offset": "+01:00"},
+                {"files": "IMG_*.jpg", "offset": "-00:30"}
+            ]"#,
+        )
+        .unwrap();
+        let find =
+            |camera, name| super::find_time_offset(&offsets, camera, name);
+        assert_eq!(
+            find(Some("Canon EOS 550D"), "IMG_0001.jpg"),
+            Some(Duration::hours(1)),
+        );
+        assert_eq!(find(None, "IMG_0001.jpg"), Some(-Duration::minutes(30)));
+        assert_eq!(find(Some("Pixel 3"), "PXL_0001.jpg"), None);
     }
 }
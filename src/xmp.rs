@@ -0,0 +1,189 @@
+//! Reading of XMP metadata, from sidecar files or embedded in images
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+const XMP_NS: &str = "http://ns.adobe.com/xap/1.0/";
+const LR_NS: &str = "http://ns.adobe.com/lightroom/1.0/";
+
+/// Embedded XMP packets are stored in the first segments of the file, there
+/// is no need to read the whole image to find them.
+const EMBEDDED_XMP_MAX_OFFSET: u64 = 256 * 1024;
+
+/// The subset of XMP metadata used by phototex
+#[derive(Debug, Default, Clone)]
+pub struct XmpMeta {
+    pub description: Option<String>,
+    /// Number of stars, -1 for rejected images
+    pub rating: Option<i32>,
+    /// Keywords (`dc:subject`), along with the hierarchical ones written by
+    /// darktable and Lightroom, such as `places|France`
+    pub tags: Vec<String>,
+}
+
+/// Path of the sidecar file for an image, `IMG_1234.jpg.xmp` as written by
+/// darktable or digiKam, or `IMG_1234.xmp`
+fn sidecar_path(image: &Path) -> Option<PathBuf> {
+    let mut long_name = image.as_os_str().to_owned();
+    long_name.push(".xmp");
+    let candidates = [PathBuf::from(long_name), image.with_extension("xmp")];
+    candidates.iter().find(|p| p.is_file()).cloned()
+}
+
+fn extract_packet(data: &[u8]) -> Option<&str> {
+    let start_tag = b"<x:xmpmeta";
+    let end_tag = b"</x:xmpmeta>";
+    let start = data.windows(start_tag.len()).position(|w| w == start_tag)?;
+    let end = data[start..]
+        .windows(end_tag.len())
+        .position(|w| w == end_tag)?;
+    std::str::from_utf8(&data[start..start + end + end_tag.len()]).ok()
+}
+
+fn read_packet(path: &Path, max_len: Option<u64>) -> Option<String> {
+    let f = std::fs::File::open(path).ok()?;
+    let mut data = Vec::new();
+    match max_len {
+        Some(max_len) => f.take(max_len).read_to_end(&mut data),
+        None => std::io::BufReader::new(f).read_to_end(&mut data),
+    }
+    .ok()?;
+    extract_packet(&data).map(str::to_string)
+}
+
+/// Items of a bag or sequence property
+fn list_items(node: roxmltree::Node) -> Vec<String> {
+    node.descendants()
+        .filter(|n| n.has_tag_name((RDF_NS, "li")))
+        .filter_map(|n| n.text())
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_rating(rating: &str) -> Option<i32> {
+    rating
+        .trim()
+        .parse::<f32>()
+        .map(|r| r.round() as i32)
+        .map_err(|_| log::warn!("Invalid XMP rating: {:?}", rating))
+        .ok()
+}
+
+/// Text of a language alternative property, preferring the default language
+fn alt_text(node: roxmltree::Node) -> Option<String> {
+    let items: Vec<_> = node
+        .descendants()
+        .filter(|n| n.has_tag_name((RDF_NS, "li")))
+        .collect();
+    let item = items
+        .iter()
+        .find(|n| n.attribute((XML_NS, "lang")) == Some("x-default"))
+        .or_else(|| items.first());
+    let text = match item {
+        Some(item) => item.text(),
+        None => node.text(),
+    };
+    text.map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+}
+
+impl XmpMeta {
+    pub fn parse(packet: &str) -> Option<XmpMeta> {
+        let doc = roxmltree::Document::parse(packet)
+            .map_err(|e| log::warn!("Could not parse XMP metadata: {}", e))
+            .ok()?;
+        let mut meta = XmpMeta::default();
+        for node in doc.descendants() {
+            if node.has_tag_name((DC_NS, "description")) {
+                meta.description = alt_text(node);
+            } else if let Some(description) =
+                node.attribute((DC_NS, "description"))
+            {
+                meta.description = Some(description.trim().to_string());
+            }
+            if node.has_tag_name((XMP_NS, "Rating")) {
+                meta.rating = node.text().and_then(parse_rating);
+            } else if let Some(rating) = node.attribute((XMP_NS, "Rating")) {
+                meta.rating = parse_rating(rating);
+            }
+            if node.has_tag_name((DC_NS, "subject"))
+                || node.has_tag_name((LR_NS, "hierarchicalSubject"))
+            {
+                meta.tags.extend(list_items(node));
+            }
+        }
+        Some(meta)
+    }
+
+    /// Read the XMP metadata of an image, from its sidecar file if any, or
+    /// else from the image itself
+    pub fn read(image: &Path) -> Option<XmpMeta> {
+        let packet = match sidecar_path(image) {
+            Some(sidecar) => read_packet(&sidecar, None),
+            None => read_packet(image, Some(EMBEDDED_XMP_MAX_OFFSET)),
+        }?;
+        XmpMeta::parse(&packet)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn parse_description() {
+        let data = br#"garbage<x:xmpmeta xmlns:x="adobe:ns:meta/">
+          <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+            <rdf:Description rdf:about=""
+                xmlns:dc="http://purl.org/dc/elements/1.1/">
+              <dc:description>
+                <rdf:Alt>
+                  <rdf:li xml:lang="fr-FR">Le phare</rdf:li>
+                  <rdf:li xml:lang="x-default">The lighthouse</rdf:li>
+                </rdf:Alt>
+              </dc:description>
+            </rdf:Description>
+          </rdf:RDF>
+        </x:xmpmeta>more garbage"#;
+        let packet = super::extract_packet(data).unwrap();
+        let meta = super::XmpMeta::parse(packet).unwrap();
+        assert_eq!(meta.description.as_deref(), Some("The lighthouse"));
+        assert_eq!(meta.rating, None);
+        assert!(meta.tags.is_empty());
+    }
+
+    #[test]
+    fn parse_rating_and_tags() {
+        // as written by darktable
+        let packet = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+          <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+            <rdf:Description rdf:about=""
+                xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+                xmlns:dc="http://purl.org/dc/elements/1.1/"
+                xmlns:lr="http://ns.adobe.com/lightroom/1.0/"
+                xmp:Rating="3">
+              <dc:subject>
+                <rdf:Bag>
+                  <rdf:li>family</rdf:li>
+                </rdf:Bag>
+              </dc:subject>
+              <lr:hierarchicalSubject>
+                <rdf:Bag>
+                  <rdf:li>people|family</rdf:li>
+                </rdf:Bag>
+              </lr:hierarchicalSubject>
+            </rdf:Description>
+          </rdf:RDF>
+        </x:xmpmeta>"#;
+        let meta = super::XmpMeta::parse(packet).unwrap();
+        assert_eq!(meta.rating, Some(3));
+        assert_eq!(meta.tags, vec!["family", "people|family"]);
+        let packet = packet
+            .replace(r#"xmp:Rating="3">"#, "><xmp:Rating>-1</xmp:Rating>");
+        let meta = super::XmpMeta::parse(&packet).unwrap();
+        assert_eq!(meta.rating, Some(-1));
+    }
+}